@@ -0,0 +1,544 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Formatter, Write as _};
+use std::rc::{Rc, Weak};
+
+use super::{dataset_attr, DomBackend, PropertyValue};
+use crate::vdom::{escape, RAW_TEXT_ELEMENTS, VOID_ELEMENTS};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Property {
+	String(String),
+	Bool(bool),
+}
+
+enum Data {
+	Text(String),
+	Comment(String),
+	Element {
+		tag: String,
+		/// `None` for HTML elements.
+		namespace: Option<String>,
+		attributes: BTreeMap<String, String>,
+		properties: BTreeMap<String, Property>,
+		styles: BTreeMap<String, String>,
+		children: Vec<MemoryNode>,
+		/// The HTML set with [`DomBackend::set_inner_html`], which is kept as is rather than parsed into `children`.
+		inner_html: Option<String>,
+	},
+}
+
+struct Inner {
+	parent: Weak<RefCell<Inner>>,
+	data: Data,
+}
+
+/// A handle to a node in a [`MemoryDom`].
+///
+/// Like handles to nodes in the browser's DOM, handles compare equal if they refer to the same node.
+/// The `Debug` representation resembles HTML, which is convenient for assertions.
+#[derive(Clone)]
+pub struct MemoryNode(Rc<RefCell<Inner>>);
+
+impl MemoryNode {
+	fn new(data: Data) -> Self {
+		Self(Rc::new(RefCell::new(Inner {
+			parent: Weak::new(),
+			data,
+		})))
+	}
+
+	/// Get the tag of this element, or `None` if this is a text or comment node.
+	#[must_use]
+	pub fn tag(&self) -> Option<String> {
+		match &self.0.borrow().data {
+			Data::Text(..) | Data::Comment(..) => None,
+			Data::Element { tag, .. } => Some(tag.clone()),
+		}
+	}
+
+	/// Get the namespace of this element, or `None` if this is an HTML element or a text node.
+	#[must_use]
+	pub fn namespace(&self) -> Option<String> {
+		match &self.0.borrow().data {
+			Data::Text(..) | Data::Comment(..) => None,
+			Data::Element { namespace, .. } => namespace.clone(),
+		}
+	}
+
+	/// Get the content of this text node, or `None` if this is not a text node.
+	#[must_use]
+	pub fn text(&self) -> Option<String> {
+		match &self.0.borrow().data {
+			Data::Text(text) => Some(text.clone()),
+			Data::Element { .. } | Data::Comment(..) => None,
+		}
+	}
+
+	/// Get the content of this comment node, or `None` if this is not a comment node.
+	#[must_use]
+	pub fn comment(&self) -> Option<String> {
+		match &self.0.borrow().data {
+			Data::Comment(text) => Some(text.clone()),
+			Data::Element { .. } | Data::Text(..) => None,
+		}
+	}
+
+	/// Get the value of the attribute `attr`, or `None` if it is not present or this is a text or comment node.
+	#[must_use]
+	pub fn attribute(&self, attr: &str) -> Option<String> {
+		match &self.0.borrow().data {
+			Data::Text(..) | Data::Comment(..) => None,
+			Data::Element { attributes, .. } => attributes.get(attr).cloned(),
+		}
+	}
+
+	/// Get the value of the string property `name`, or `None` if it has not been set to a string or this is a text or comment node.
+	#[must_use]
+	pub fn string_property(&self, name: &str) -> Option<String> {
+		match &self.0.borrow().data {
+			Data::Element { properties, .. } => match properties.get(name)? {
+				Property::String(value) => Some(value.clone()),
+				Property::Bool(..) => None,
+			},
+			Data::Text(..) | Data::Comment(..) => None,
+		}
+	}
+
+	/// Get the value of the boolean property `name`, or `None` if it has not been set to a boolean or this is a text or comment node.
+	#[must_use]
+	pub fn bool_property(&self, name: &str) -> Option<bool> {
+		match &self.0.borrow().data {
+			Data::Element { properties, .. } => match properties.get(name)? {
+				Property::Bool(value) => Some(*value),
+				Property::String(..) => None,
+			},
+			Data::Text(..) | Data::Comment(..) => None,
+		}
+	}
+
+	/// Get the value of the inline style property `prop`, or `None` if it is not set or this is a text or comment node.
+	#[must_use]
+	pub fn style(&self, prop: &str) -> Option<String> {
+		match &self.0.borrow().data {
+			Data::Text(..) | Data::Comment(..) => None,
+			Data::Element { styles, .. } => styles.get(prop).cloned(),
+		}
+	}
+
+	/// Get all the attributes of this element, sorted by name.
+	///
+	/// Text and comment nodes have no attributes.
+	#[must_use]
+	pub fn attributes(&self) -> Vec<(String, String)> {
+		match &self.0.borrow().data {
+			Data::Text(..) | Data::Comment(..) => Vec::new(),
+			Data::Element { attributes, .. } => attributes
+				.iter()
+				.map(|(attr, value)| (attr.clone(), value.clone()))
+				.collect(),
+		}
+	}
+
+	/// Serialize the content of this element to HTML, like `innerHTML` in the browser, or get `None` if this is a text or comment node.
+	///
+	/// HTML set with [`DomBackend::set_inner_html`] is not parsed, so it is returned as is and is not reflected in [`children`](Self::children).
+	#[must_use]
+	pub fn inner_html(&self) -> Option<String> {
+		let mut ret = String::new();
+		match &self.0.borrow().data {
+			Data::Text(..) | Data::Comment(..) => return None,
+			Data::Element {
+				inner_html: Some(inner_html),
+				..
+			} => ret.clone_from(inner_html),
+			Data::Element {
+				tag,
+				namespace,
+				children,
+				..
+			} => {
+				let raw_text = namespace.is_none() && RAW_TEXT_ELEMENTS.contains(&tag.as_str());
+				for child in children {
+					child.write_html(&mut ret, raw_text);
+				}
+			}
+		}
+		Some(ret)
+	}
+
+	/// Serialize this node to HTML, like `outerHTML` in the browser.
+	///
+	/// `raw_text` is set if this is within a raw text element, whose text isn't escaped.
+	fn write_html(&self, out: &mut String, raw_text: bool) {
+		match &self.0.borrow().data {
+			Data::Text(text) if raw_text => out.push_str(text),
+			Data::Text(text) => escape(out, text),
+			Data::Comment(text) => write!(out, "<!--{text}-->").unwrap(),
+			Data::Element {
+				tag,
+				namespace,
+				attributes,
+				styles,
+				..
+			} => {
+				write!(out, "<{tag}").unwrap();
+				for (attr, value) in attributes {
+					write!(out, " {attr}=\"").unwrap();
+					escape(out, value);
+					out.push('"');
+				}
+				// the browser reflects the style properties in the `style` attribute
+				if !styles.is_empty() && !attributes.contains_key("style") {
+					let style: Vec<_> = styles
+						.iter()
+						.map(|(prop, value)| format!("{prop}: {value};"))
+						.collect();
+					out.push_str(" style=\"");
+					escape(out, &style.join(" "));
+					out.push('"');
+				}
+				out.push('>');
+
+				// in SVG and MathML, void elements are serialized with an end tag
+				if namespace.is_none() && VOID_ELEMENTS.contains(&tag.as_str()) {
+					return;
+				}
+				out.push_str(&self.inner_html().unwrap());
+				write!(out, "</{tag}>").unwrap();
+			}
+		}
+	}
+
+	/// Get the children of this element.
+	///
+	/// Text and comment nodes have no children.
+	#[must_use]
+	pub fn children(&self) -> Vec<MemoryNode> {
+		match &self.0.borrow().data {
+			Data::Text(..) | Data::Comment(..) => Vec::new(),
+			Data::Element { children, .. } => children.clone(),
+		}
+	}
+
+	/// Get the parent of this node, if it has been inserted into another node.
+	#[must_use]
+	pub fn parent(&self) -> Option<MemoryNode> {
+		self.0.borrow().parent.upgrade().map(Self)
+	}
+
+	fn with_children<R>(&self, f: impl FnOnce(&mut Vec<MemoryNode>) -> R) -> R {
+		match &mut self.0.borrow_mut().data {
+			Data::Text(..) | Data::Comment(..) => panic!("text and comment nodes cannot have children"),
+			Data::Element { children, .. } => f(children),
+		}
+	}
+
+	fn with_styles<R>(&self, f: impl FnOnce(&mut BTreeMap<String, String>) -> R) -> R {
+		match &mut self.0.borrow_mut().data {
+			Data::Text(..) | Data::Comment(..) => panic!("text and comment nodes cannot have styles"),
+			Data::Element { styles, .. } => f(styles),
+		}
+	}
+
+	fn with_attributes<R>(&self, f: impl FnOnce(&mut BTreeMap<String, String>) -> R) -> R {
+		match &mut self.0.borrow_mut().data {
+			Data::Text(..) | Data::Comment(..) => panic!("text and comment nodes cannot have attributes"),
+			Data::Element { attributes, .. } => f(attributes),
+		}
+	}
+
+	fn position_in(&self, children: &[MemoryNode]) -> usize {
+		children
+			.iter()
+			.position(|child| child == self)
+			.expect("node is not a child of the parent")
+	}
+
+	fn set_parent(&self, parent: Option<&MemoryNode>) {
+		self.0.borrow_mut().parent = parent.map_or_else(Weak::new, |parent| Rc::downgrade(&parent.0));
+	}
+
+	fn detach(&self) {
+		if let Some(parent) = self.parent() {
+			parent.with_children(|children| children.remove(self.position_in(children)));
+			self.set_parent(None);
+		}
+	}
+}
+
+impl PartialEq for MemoryNode {
+	fn eq(&self, other: &Self) -> bool {
+		Rc::ptr_eq(&self.0, &other.0)
+	}
+}
+
+impl Eq for MemoryNode {}
+
+impl Debug for MemoryNode {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+		match &self.0.borrow().data {
+			Data::Text(text) => write!(formatter, "{text:?}"),
+			Data::Comment(text) => write!(formatter, "<!--{text}-->"),
+			Data::Element {
+				tag,
+				namespace: _,
+				attributes,
+				properties,
+				styles,
+				children,
+				inner_html,
+			} => {
+				write!(formatter, "<{tag}")?;
+				for (attr, value) in attributes {
+					write!(formatter, " {attr}={value:?}")?;
+				}
+				for (name, value) in properties {
+					match value {
+						Property::String(value) => write!(formatter, " .{name}={value:?}")?,
+						Property::Bool(value) => write!(formatter, " .{name}={value}")?,
+					}
+				}
+				for (prop, value) in styles {
+					write!(formatter, " style.{prop}={value:?}")?;
+				}
+				formatter.write_str(">")?;
+				for child in children {
+					write!(formatter, "{child:?}")?;
+				}
+				if let Some(inner_html) = inner_html {
+					formatter.write_str(inner_html)?;
+				}
+				write!(formatter, "</{tag}>")
+			}
+		}
+	}
+}
+
+/// A simple in-memory DOM.
+///
+/// Create the root node with [`DomBackend::create_element`] and inspect the result through the methods of [`MemoryNode`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryDom;
+
+impl DomBackend for MemoryDom {
+	type Node = MemoryNode;
+
+	fn create_element(&self, tag: &str) -> MemoryNode {
+		MemoryNode::new(Data::Element {
+			tag: tag.to_owned(),
+			namespace: None,
+			attributes: BTreeMap::new(),
+			properties: BTreeMap::new(),
+			styles: BTreeMap::new(),
+			children: Vec::new(),
+			inner_html: None,
+		})
+	}
+
+	fn create_element_ns(&self, namespace: &str, tag: &str) -> MemoryNode {
+		MemoryNode::new(Data::Element {
+			tag: tag.to_owned(),
+			namespace: Some(namespace.to_owned()),
+			attributes: BTreeMap::new(),
+			properties: BTreeMap::new(),
+			styles: BTreeMap::new(),
+			children: Vec::new(),
+			inner_html: None,
+		})
+	}
+
+	fn create_text(&self, text: &str) -> MemoryNode {
+		MemoryNode::new(Data::Text(text.to_owned()))
+	}
+
+	fn create_comment(&self, text: &str) -> MemoryNode {
+		MemoryNode::new(Data::Comment(text.to_owned()))
+	}
+
+	fn set_text(&self, text_node: &MemoryNode, text: &str) {
+		match &mut text_node.0.borrow_mut().data {
+			Data::Text(old) | Data::Comment(old) => text.clone_into(old),
+			Data::Element { .. } => panic!("elements do not have text content"),
+		}
+	}
+
+	fn set_attribute(&self, element: &MemoryNode, attr: &str, value: &str) {
+		element.with_attributes(|attributes| attributes.insert(attr.to_owned(), value.to_owned()));
+	}
+
+	fn remove_attribute(&self, element: &MemoryNode, attr: &str) {
+		element.with_attributes(|attributes| attributes.remove(attr));
+		if attr == "style" {
+			element.with_styles(BTreeMap::clear);
+		}
+	}
+
+	fn set_property(&self, element: &MemoryNode, name: &str, value: PropertyValue<'_>) {
+		let value = match value {
+			PropertyValue::String(value) => Property::String(value.to_owned()),
+			PropertyValue::Bool(value) => Property::Bool(value),
+		};
+		match &mut element.0.borrow_mut().data {
+			Data::Text(..) | Data::Comment(..) => panic!("text and comment nodes cannot have properties"),
+			Data::Element { properties, .. } => {
+				properties.insert(name.to_owned(), value);
+			}
+		}
+	}
+
+	fn set_style(&self, element: &MemoryNode, prop: &str, value: &str) {
+		element.with_styles(|styles| styles.insert(prop.to_owned(), value.to_owned()));
+	}
+
+	fn remove_style(&self, element: &MemoryNode, prop: &str) {
+		element.with_styles(|styles| styles.remove(prop));
+	}
+
+	// like the browser, classes are stored in the `class` attribute
+	fn add_class(&self, element: &MemoryNode, name: &str) {
+		element.with_attributes(|attributes| {
+			let class = attributes.entry("class".to_owned()).or_default();
+			if !class.split_ascii_whitespace().any(|token| token == name) {
+				if !class.is_empty() {
+					class.push(' ');
+				}
+				class.push_str(name);
+			}
+		});
+	}
+
+	fn remove_class(&self, element: &MemoryNode, name: &str) {
+		element.with_attributes(|attributes| {
+			if let Some(class) = attributes.get_mut("class") {
+				*class = class
+					.split_ascii_whitespace()
+					.filter(|&token| token != name)
+					.collect::<Vec<_>>()
+					.join(" ");
+			}
+		});
+	}
+
+	fn set_inner_html(&self, element: &MemoryNode, html: &str) {
+		for child in element.with_children(std::mem::take) {
+			child.set_parent(None);
+		}
+		match &mut element.0.borrow_mut().data {
+			Data::Text(..) | Data::Comment(..) => panic!("text and comment nodes cannot have inner HTML"),
+			Data::Element { inner_html, .. } => *inner_html = Some(html.to_owned()),
+		}
+	}
+
+	fn set_dataset(&self, element: &MemoryNode, key: &str, value: &str) {
+		self.set_attribute(element, &dataset_attr(key), value);
+	}
+
+	fn remove_dataset(&self, element: &MemoryNode, key: &str) {
+		self.remove_attribute(element, &dataset_attr(key));
+	}
+
+	fn tag(&self, node: &MemoryNode) -> Option<String> {
+		node.tag()
+	}
+
+	fn namespace(&self, node: &MemoryNode) -> Option<String> {
+		node.namespace()
+	}
+
+	fn text(&self, node: &MemoryNode) -> Option<String> {
+		node.text()
+	}
+
+	fn comment(&self, node: &MemoryNode) -> Option<String> {
+		node.comment()
+	}
+
+	fn attribute(&self, node: &MemoryNode, attr: &str) -> Option<String> {
+		node.attribute(attr)
+	}
+
+	fn attribute_names(&self, node: &MemoryNode) -> Vec<String> {
+		match &node.0.borrow().data {
+			Data::Text(..) | Data::Comment(..) => Vec::new(),
+			Data::Element {
+				attributes, styles, ..
+			} => {
+				let mut names: Vec<_> = attributes.keys().cloned().collect();
+				// the browser reflects the style properties in the `style` attribute
+				if !styles.is_empty() && !attributes.contains_key("style") {
+					names.push("style".to_owned());
+				}
+				names
+			}
+		}
+	}
+
+	fn dataset(&self, node: &MemoryNode, key: &str) -> Option<String> {
+		node.attribute(&dataset_attr(key))
+	}
+
+	fn inner_html(&self, node: &MemoryNode) -> Option<String> {
+		node.inner_html()
+	}
+
+	fn children(&self, parent: &MemoryNode) -> Vec<MemoryNode> {
+		parent.children()
+	}
+
+	fn insert_child(&self, parent: &MemoryNode, child: &MemoryNode, before: Option<&MemoryNode>) {
+		child.detach();
+		parent.with_children(|children| {
+			let index = before.map_or(children.len(), |before| before.position_in(children));
+			children.insert(index, child.clone());
+		});
+		child.set_parent(Some(parent));
+	}
+
+	fn replace_child(&self, parent: &MemoryNode, new: &MemoryNode, old: &MemoryNode) {
+		new.detach();
+		parent.with_children(|children| {
+			let index = old.position_in(children);
+			children[index] = new.clone();
+		});
+		old.set_parent(None);
+		new.set_parent(Some(parent));
+	}
+
+	fn remove_child(&self, parent: &MemoryNode, child: &MemoryNode) {
+		parent.with_children(|children| children.remove(child.position_in(children)));
+		child.set_parent(None);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::MemoryDom;
+	use crate::backend::DomBackend;
+
+	#[test]
+	fn serialize() {
+		let root = MemoryDom.create_element("div");
+		let p = MemoryDom.create_element("p");
+		MemoryDom.set_attribute(&p, "title", "\"a\" & b");
+		MemoryDom.set_style(&p, "color", "red");
+		MemoryDom.insert_child(&p, &MemoryDom.create_text("1 < 2"), None);
+		MemoryDom.insert_child(&root, &p, None);
+		MemoryDom.insert_child(&root, &MemoryDom.create_element("br"), None);
+		MemoryDom.insert_child(&root, &MemoryDom.create_comment("note"), None);
+		let script = MemoryDom.create_element("script");
+		MemoryDom.insert_child(&script, &MemoryDom.create_text("1 < 2"), None);
+		MemoryDom.insert_child(&root, &script, None);
+
+		assert_eq!(
+			root.inner_html().as_deref(),
+			Some(
+				r#"<p title="&quot;a&quot; &amp; b" style="color: red;">1 &lt; 2</p><br><!--note--><script>1 < 2</script>"#
+			),
+		);
+
+		MemoryDom.set_inner_html(&root, "<b>as is</b>");
+		assert_eq!(root.inner_html().as_deref(), Some("<b>as is</b>"));
+		assert_eq!(MemoryDom.create_text("text").inner_html(), None);
+	}
+}
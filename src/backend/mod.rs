@@ -0,0 +1,140 @@
+//! Abstracts over the DOM that the virtual DOM is applied to.
+//!
+//! [`WebDom`] is the browser's DOM and is what [`run`](crate::run) uses.
+//! [`MemoryDom`] is a simple in-memory tree, which allows exercising the virtual DOM outside of the browser, such as in tests.
+
+pub use self::memory::{MemoryDom, MemoryNode};
+pub use self::web::WebDom;
+
+mod memory;
+mod web;
+
+/// The value of a DOM property, as opposed to an attribute.
+///
+/// For form elements, properties such as `value` and `checked` control the current state, whereas the corresponding attributes only control the initial state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyValue<'a> {
+	/// A string property, such as `value`.
+	String(&'a str),
+	/// A boolean property, such as `checked`.
+	Bool(bool),
+}
+
+impl PropertyValue<'_> {
+	/// The value that a property of this type is reset to when it is no longer set.
+	pub(crate) fn reset(self) -> Self {
+		match self {
+			Self::String(..) => Self::String(""),
+			Self::Bool(..) => Self::Bool(false),
+		}
+	}
+
+	/// Copy the value into `bump`.
+	pub(crate) fn copy_into(self, bump: &bumpalo::Bump) -> PropertyValue<'_> {
+		match self {
+			Self::String(value) => PropertyValue::String(bump.alloc_str(value)),
+			Self::Bool(value) => PropertyValue::Bool(value),
+		}
+	}
+}
+
+/// Convert a dataset key to the name of the corresponding attribute, as the browser does.
+///
+/// Going through the attribute rather than the dataset works for every element, since only HTML, SVG, and `MathML` elements have a dataset.
+pub(crate) fn dataset_attr(key: &str) -> String {
+	let mut ret = String::from("data-");
+	for ch in key.chars() {
+		if ch.is_ascii_uppercase() {
+			ret.push('-');
+			ret.push(ch.to_ascii_lowercase());
+		} else {
+			ret.push(ch);
+		}
+	}
+	ret
+}
+
+/// The operations needed to apply the virtual DOM to a DOM.
+///
+/// The methods are infallible; if the underlying DOM operation fails, the implementation should panic.
+pub trait DomBackend {
+	/// A handle to a node in the DOM.
+	///
+	/// Cloning a handle must not clone the node itself, and handles must compare equal if they refer to the same node.
+	type Node: Clone + PartialEq + 'static;
+
+	/// Create an HTML element with the tag `tag`.
+	fn create_element(&self, tag: &str) -> Self::Node;
+	/// Create an element with the tag `tag` in the namespace `namespace`, such as [`SVG_NAMESPACE`](crate::vdom::SVG_NAMESPACE).
+	fn create_element_ns(&self, namespace: &str, tag: &str) -> Self::Node;
+	/// Create a text node with the content `text`.
+	fn create_text(&self, text: &str) -> Self::Node;
+	/// Create a comment node with the content `text`.
+	fn create_comment(&self, text: &str) -> Self::Node;
+	/// Replace the content of a text or comment node.
+	fn set_text(&self, text_node: &Self::Node, text: &str);
+
+	/// Add an attribute to `element`, replacing the old value if one was present.
+	fn set_attribute(&self, element: &Self::Node, attr: &str, value: &str);
+	/// Remove an attribute from `element`.
+	///
+	/// Removing `style` removes every property of the inline style, as in the browser.
+	fn remove_attribute(&self, element: &Self::Node, attr: &str);
+	/// Set a property of `element`.
+	///
+	/// Since the property may have been changed by the user, this is called on hydration even if it has the right value, so implementations should avoid side effects if the value is already correct.
+	fn set_property(&self, element: &Self::Node, name: &str, value: PropertyValue<'_>);
+
+	/// Set a property of the inline style of `element`, such as `color`.
+	fn set_style(&self, element: &Self::Node, prop: &str, value: &str);
+	/// Remove a property from the inline style of `element`.
+	fn remove_style(&self, element: &Self::Node, prop: &str);
+
+	/// Add a class to `element`, if it doesn't already have it.
+	///
+	/// `name` is never empty and never contains whitespace.
+	fn add_class(&self, element: &Self::Node, name: &str);
+	/// Remove a class from `element`, if it has it.
+	fn remove_class(&self, element: &Self::Node, name: &str);
+
+	/// Replace the children of `element` with `html`, parsed as HTML.
+	fn set_inner_html(&self, element: &Self::Node, html: &str);
+	/// Set an entry in the dataset of `element`, i.e., the `data-*` attributes.
+	fn set_dataset(&self, element: &Self::Node, key: &str, value: &str);
+	/// Remove an entry from the dataset of `element`.
+	fn remove_dataset(&self, element: &Self::Node, key: &str);
+
+	/// Get the tag of `node`, or `None` if it is not an element.
+	///
+	/// The tags of HTML elements are in lowercase, but other elements keep their case, such as `foreignObject`.
+	fn tag(&self, node: &Self::Node) -> Option<String>;
+	/// Get the namespace of `node`, or `None` if it is an HTML element or not an element.
+	fn namespace(&self, node: &Self::Node) -> Option<String>;
+	/// Get the content of `node`, or `None` if it is not a text node.
+	fn text(&self, node: &Self::Node) -> Option<String>;
+	/// Get the content of `node`, or `None` if it is not a comment node.
+	fn comment(&self, node: &Self::Node) -> Option<String>;
+	/// Get the value of an attribute of `node`, or `None` if it is not present or `node` is not an element.
+	fn attribute(&self, node: &Self::Node, attr: &str) -> Option<String>;
+	/// Get the names of the attributes of `node`, or an empty list if it is not an element.
+	///
+	/// This includes `class` and `style` if they are present.
+	fn attribute_names(&self, node: &Self::Node) -> Vec<String>;
+	/// Get an entry in the dataset of `node`, or `None` if it is not present or `node` is not an element.
+	fn dataset(&self, node: &Self::Node, key: &str) -> Option<String>;
+	/// Get the children of `node` serialized as HTML, or `None` if it is not an element.
+	///
+	/// This need not match the HTML passed to [`set_inner_html`](Self::set_inner_html) exactly, since the browser normalizes it.
+	fn inner_html(&self, node: &Self::Node) -> Option<String>;
+
+	/// Get the current children of `parent`, in order.
+	fn children(&self, parent: &Self::Node) -> Vec<Self::Node>;
+	/// Insert `child` into `parent` before `before`, or at the end if `before` is `None`.
+	///
+	/// If `child` is already in the DOM, it is moved.
+	fn insert_child(&self, parent: &Self::Node, child: &Self::Node, before: Option<&Self::Node>);
+	/// Replace `old`, a child of `parent`, with `new`.
+	fn replace_child(&self, parent: &Self::Node, new: &Self::Node, old: &Self::Node);
+	/// Remove `child` from `parent`.
+	fn remove_child(&self, parent: &Self::Node, child: &Self::Node);
+}
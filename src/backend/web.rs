@@ -0,0 +1,164 @@
+use wasm_bindgen::{JsCast as _, JsValue};
+use web_sys::Node;
+
+use super::{dataset_attr, DomBackend, PropertyValue};
+use crate::vdom::HTML_NAMESPACE;
+
+fn document() -> web_sys::Document {
+	web_sys::window().unwrap().document().unwrap()
+}
+
+fn as_element(node: &Node) -> &web_sys::Element {
+	node.dyn_ref().unwrap()
+}
+
+/// The inline style of `element`.
+fn style(element: &Node) -> web_sys::CssStyleDeclaration {
+	// `web_sys` only binds `style` for HTML and SVG elements, but MathML elements have it too
+	js_sys::Reflect::get(element, &JsValue::from_str("style"))
+		.unwrap()
+		.unchecked_into()
+}
+
+/// The browser's DOM, through `web_sys`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WebDom;
+
+impl DomBackend for WebDom {
+	type Node = Node;
+
+	fn create_element(&self, tag: &str) -> Node {
+		document().create_element(tag).unwrap().into()
+	}
+
+	fn create_element_ns(&self, namespace: &str, tag: &str) -> Node {
+		document()
+			.create_element_ns(Some(namespace), tag)
+			.unwrap()
+			.into()
+	}
+
+	fn create_text(&self, text: &str) -> Node {
+		web_sys::Text::new_with_data(text).unwrap().into()
+	}
+
+	fn create_comment(&self, text: &str) -> Node {
+		document().create_comment(text).into()
+	}
+
+	fn set_text(&self, text_node: &Node, text: &str) {
+		text_node.set_node_value(Some(text));
+	}
+
+	fn set_attribute(&self, element: &Node, attr: &str, value: &str) {
+		as_element(element).set_attribute(attr, value).unwrap();
+	}
+
+	fn remove_attribute(&self, element: &Node, attr: &str) {
+		as_element(element).remove_attribute(attr).unwrap();
+	}
+
+	fn set_property(&self, element: &Node, name: &str, value: PropertyValue<'_>) {
+		let name = JsValue::from_str(name);
+		let value = match value {
+			PropertyValue::String(value) => JsValue::from_str(value),
+			PropertyValue::Bool(value) => JsValue::from_bool(value),
+		};
+		// writing the same value is not always a no-op; for example, it moves the cursor of a text input to the end
+		if js_sys::Reflect::get(element, &name).ok().as_ref() != Some(&value) {
+			js_sys::Reflect::set(element, &name, &value).unwrap();
+		}
+	}
+
+	fn set_style(&self, element: &Node, prop: &str, value: &str) {
+		style(element).set_property(prop, value).unwrap();
+	}
+
+	fn remove_style(&self, element: &Node, prop: &str) {
+		style(element).remove_property(prop).unwrap();
+	}
+
+	fn add_class(&self, element: &Node, name: &str) {
+		as_element(element).class_list().add_1(name).unwrap();
+	}
+
+	fn remove_class(&self, element: &Node, name: &str) {
+		as_element(element).class_list().remove_1(name).unwrap();
+	}
+
+	fn set_inner_html(&self, element: &Node, html: &str) {
+		as_element(element).set_inner_html(html);
+	}
+
+	fn set_dataset(&self, element: &Node, key: &str, value: &str) {
+		self.set_attribute(element, &dataset_attr(key), value);
+	}
+
+	fn remove_dataset(&self, element: &Node, key: &str) {
+		self.remove_attribute(element, &dataset_attr(key));
+	}
+
+	fn tag(&self, node: &Node) -> Option<String> {
+		node
+			.dyn_ref::<web_sys::Element>()
+			.map(web_sys::Element::local_name)
+	}
+
+	fn namespace(&self, node: &Node) -> Option<String> {
+		node
+			.dyn_ref::<web_sys::Element>()?
+			.namespace_uri()
+			.filter(|namespace| namespace != HTML_NAMESPACE)
+	}
+
+	fn text(&self, node: &Node) -> Option<String> {
+		(node.node_type() == Node::TEXT_NODE).then(|| node.node_value().unwrap_or_default())
+	}
+
+	fn comment(&self, node: &Node) -> Option<String> {
+		(node.node_type() == Node::COMMENT_NODE).then(|| node.node_value().unwrap_or_default())
+	}
+
+	fn attribute(&self, node: &Node, attr: &str) -> Option<String> {
+		node.dyn_ref::<web_sys::Element>()?.get_attribute(attr)
+	}
+
+	fn attribute_names(&self, node: &Node) -> Vec<String> {
+		node
+			.dyn_ref::<web_sys::Element>()
+			.map_or_else(Vec::new, |element| {
+				element
+					.get_attribute_names()
+					.iter()
+					.filter_map(|name| name.as_string())
+					.collect()
+			})
+	}
+
+	fn dataset(&self, node: &Node, key: &str) -> Option<String> {
+		self.attribute(node, &dataset_attr(key))
+	}
+
+	fn inner_html(&self, node: &Node) -> Option<String> {
+		Some(node.dyn_ref::<web_sys::Element>()?.inner_html())
+	}
+
+	fn children(&self, parent: &Node) -> Vec<Node> {
+		let children = parent.child_nodes();
+		(0..children.length())
+			.map(|i| children.item(i).unwrap())
+			.collect()
+	}
+
+	fn insert_child(&self, parent: &Node, child: &Node, before: Option<&Node>) {
+		parent.insert_before(child, before).unwrap();
+	}
+
+	fn replace_child(&self, parent: &Node, new: &Node, old: &Node) {
+		parent.replace_child(new, old).unwrap();
+	}
+
+	fn remove_child(&self, parent: &Node, child: &Node) {
+		parent.remove_child(child).unwrap();
+	}
+}
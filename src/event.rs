@@ -1,46 +1,433 @@
+use std::cell::Cell;
+
 use wasm_bindgen::JsCast as _;
 
+use crate::backend::dataset_attr;
 use crate::id::Id;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum EventKind {
+/// A kind of DOM event that can be received by elements.
+///
+/// Listeners are only registered for the kinds of events that the app actually checks for, so checking for an event has a small cost even if it never happens.
+/// Kinds without a dedicated method, such as [`ElementBuilder::clicked`](crate::ElementBuilder::clicked), are checked for with [`ElementBuilder::received`](crate::ElementBuilder::received).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EventKind {
+	/// `click`
 	Click,
+	/// `dblclick`
+	DoubleClick,
+	/// `change`
 	Change,
+	/// `input`
+	Input,
+	/// `submit`
+	Submit,
+	/// `keydown`
+	KeyDown,
+	/// `keyup`
+	KeyUp,
+	/// `focus`
+	Focus,
+	/// `blur`
+	Blur,
+	/// `mouseover`
+	MouseOver,
+	/// `mouseout`
+	MouseOut,
+	/// `pointerdown`
+	PointerDown,
+	/// `pointerup`
+	PointerUp,
+	/// `pointermove`
+	PointerMove,
+	/// `wheel`
+	Wheel,
+	/// `mousedown`
+	MouseDown,
+	/// `mouseup`
+	MouseUp,
+	/// `mousemove`
+	MouseMove,
+	/// `mouseenter`
+	MouseEnter,
+	/// `mouseleave`
+	MouseLeave,
+	/// `contextmenu`
+	ContextMenu,
+	/// `pointercancel`
+	PointerCancel,
+	/// `touchstart`
+	TouchStart,
+	/// `touchend`
+	TouchEnd,
+	/// `touchmove`
+	TouchMove,
+	/// `touchcancel`
+	TouchCancel,
+	/// `focusin`
+	FocusIn,
+	/// `focusout`
+	FocusOut,
+	/// `scroll`
+	Scroll,
 }
 
-pub(crate) const HANDLED_EVENTS: &[(&str, EventKind)] =
-	&[("click", EventKind::Click), ("change", EventKind::Change)];
-
 impl EventKind {
+	pub(crate) const ALL: &[Self] = &[
+		Self::Click,
+		Self::DoubleClick,
+		Self::Change,
+		Self::Input,
+		Self::Submit,
+		Self::KeyDown,
+		Self::KeyUp,
+		Self::Focus,
+		Self::Blur,
+		Self::MouseOver,
+		Self::MouseOut,
+		Self::PointerDown,
+		Self::PointerUp,
+		Self::PointerMove,
+		Self::Wheel,
+		Self::MouseDown,
+		Self::MouseUp,
+		Self::MouseMove,
+		Self::MouseEnter,
+		Self::MouseLeave,
+		Self::ContextMenu,
+		Self::PointerCancel,
+		Self::TouchStart,
+		Self::TouchEnd,
+		Self::TouchMove,
+		Self::TouchCancel,
+		Self::FocusIn,
+		Self::FocusOut,
+		Self::Scroll,
+	];
+
+	/// The name of the event in the DOM.
+	#[must_use]
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Click => "click",
+			Self::DoubleClick => "dblclick",
+			Self::Change => "change",
+			Self::Input => "input",
+			Self::Submit => "submit",
+			Self::KeyDown => "keydown",
+			Self::KeyUp => "keyup",
+			Self::Focus => "focus",
+			Self::Blur => "blur",
+			Self::MouseOver => "mouseover",
+			Self::MouseOut => "mouseout",
+			Self::PointerDown => "pointerdown",
+			Self::PointerUp => "pointerup",
+			Self::PointerMove => "pointermove",
+			Self::Wheel => "wheel",
+			Self::MouseDown => "mousedown",
+			Self::MouseUp => "mouseup",
+			Self::MouseMove => "mousemove",
+			Self::MouseEnter => "mouseenter",
+			Self::MouseLeave => "mouseleave",
+			Self::ContextMenu => "contextmenu",
+			Self::PointerCancel => "pointercancel",
+			Self::TouchStart => "touchstart",
+			Self::TouchEnd => "touchend",
+			Self::TouchMove => "touchmove",
+			Self::TouchCancel => "touchcancel",
+			Self::FocusIn => "focusin",
+			Self::FocusOut => "focusout",
+			Self::Scroll => "scroll",
+		}
+	}
+
 	fn from_dom(dom: &str) -> Option<Self> {
-		HANDLED_EVENTS
+		Self::ALL.iter().copied().find(|kind| kind.name() == dom)
+	}
+
+	/// Whether events of this kind propagate from their target to its ancestors.
+	#[must_use]
+	pub fn bubbles(self) -> bool {
+		!matches!(
+			self,
+			Self::Focus | Self::Blur | Self::MouseEnter | Self::MouseLeave | Self::Scroll
+		)
+	}
+}
+
+/// The dataset key of the events whose default action an element always prevents, stored as an [`EventSet`].
+pub(crate) const PREVENT_DEFAULT_DATA_KEY: &str = "__domi_prevent";
+
+/// A set of [`EventKind`]s.
+///
+/// Each kind is a bit, so there can be no more than 32 kinds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct EventSet(u32);
+
+const _: () = assert!(EventKind::ALL.len() <= 32);
+
+impl EventSet {
+	fn bit(kind: EventKind) -> u32 {
+		1 << kind as u32
+	}
+
+	pub(crate) fn with(self, kind: EventKind) -> Self {
+		Self(self.0 | Self::bit(kind))
+	}
+
+	pub(crate) fn contains(self, kind: EventKind) -> bool {
+		self.0 & Self::bit(kind) != 0
+	}
+
+	pub(crate) fn is_empty(self) -> bool {
+		self.0 == 0
+	}
+
+	pub(crate) fn union(self, other: Self) -> Self {
+		Self(self.0 | other.0)
+	}
+
+	pub(crate) fn difference(self, other: Self) -> Self {
+		Self(self.0 & !other.0)
+	}
+
+	pub(crate) fn iter(self) -> impl Iterator<Item = EventKind> {
+		EventKind::ALL
 			.iter()
 			.copied()
-			.find(|&(name, _)| dom == name)
-			.map(|(_, val)| val)
+			.filter(move |&kind| self.contains(kind))
+	}
+}
+
+impl std::fmt::Display for EventSet {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.0.fmt(formatter)
+	}
+}
+
+impl std::str::FromStr for EventSet {
+	type Err = std::num::ParseIntError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		s.parse().map(Self)
 	}
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// The modifier keys that were held during an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Modifiers {
+	/// The Shift key.
+	pub shift: bool,
+	/// The Control key.
+	pub ctrl: bool,
+	/// The Alt (or Option) key.
+	pub alt: bool,
+	/// The Meta (Windows or Command) key.
+	pub meta: bool,
+}
+
+/// Information about a keyboard event.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeyboardInfo {
+	/// The value of the key, taking into account the keyboard layout and modifiers, such as `"a"`, `"A"`, or `"Enter"`.
+	pub key: String,
+	/// The physical key, regardless of the keyboard layout, such as `"KeyA"`.
+	pub code: String,
+	/// Whether the key is being held down such that the event is repeating.
+	pub repeat: bool,
+	/// The modifier keys that were held.
+	pub modifiers: Modifiers,
+}
+
+/// Information about a mouse or pointer event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PointerInfo {
+	/// The X coordinate of the pointer relative to the viewport.
+	pub client_x: i32,
+	/// The Y coordinate of the pointer relative to the viewport.
+	pub client_y: i32,
+	/// The X coordinate of the pointer relative to the target element.
+	pub offset_x: i32,
+	/// The Y coordinate of the pointer relative to the target element.
+	pub offset_y: i32,
+	/// The button that was pressed or released, as defined by [`MouseEvent.button`](https://developer.mozilla.org/en-US/docs/Web/API/MouseEvent/button).
+	pub button: i16,
+	/// The buttons that were held, as defined by [`MouseEvent.buttons`](https://developer.mozilla.org/en-US/docs/Web/API/MouseEvent/buttons).
+	pub buttons: u16,
+	/// The ID of the pointer, for pointer events.
+	pub pointer_id: Option<i32>,
+	/// The modifier keys that were held.
+	pub modifiers: Modifiers,
+}
+
+/// Information about a wheel event.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WheelInfo {
+	/// The amount scrolled horizontally.
+	pub delta_x: f64,
+	/// The amount scrolled vertically.
+	pub delta_y: f64,
+	/// The amount scrolled on the Z axis.
+	pub delta_z: f64,
+	/// The unit of the deltas, as defined by [`WheelEvent.deltaMode`](https://developer.mozilla.org/en-US/docs/Web/API/WheelEvent/deltaMode).
+	pub delta_mode: u32,
+	/// The state of the pointer, as wheel events are also mouse events.
+	pub pointer: PointerInfo,
+}
+
+/// The information specific to the kind of event.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum EventPayload {
+	None,
+	Keyboard(KeyboardInfo),
+	Pointer(PointerInfo),
+	Wheel(WheelInfo),
+}
+
+impl EventPayload {
+	fn from_dom(dom: &web_sys::Event) -> Self {
+		if let Some(wheel) = dom.dyn_ref::<web_sys::WheelEvent>() {
+			Self::Wheel(WheelInfo {
+				delta_x: wheel.delta_x(),
+				delta_y: wheel.delta_y(),
+				delta_z: wheel.delta_z(),
+				delta_mode: wheel.delta_mode(),
+				pointer: PointerInfo::from_dom(wheel),
+			})
+		} else if let Some(mouse) = dom.dyn_ref::<web_sys::MouseEvent>() {
+			Self::Pointer(PointerInfo::from_dom(mouse))
+		} else if let Some(keyboard) = dom.dyn_ref::<web_sys::KeyboardEvent>() {
+			Self::Keyboard(KeyboardInfo {
+				key: keyboard.key(),
+				code: keyboard.code(),
+				repeat: keyboard.repeat(),
+				modifiers: Modifiers {
+					shift: keyboard.shift_key(),
+					ctrl: keyboard.ctrl_key(),
+					alt: keyboard.alt_key(),
+					meta: keyboard.meta_key(),
+				},
+			})
+		} else {
+			Self::None
+		}
+	}
+}
+
+impl PointerInfo {
+	fn from_dom(mouse: &web_sys::MouseEvent) -> Self {
+		Self {
+			client_x: mouse.client_x(),
+			client_y: mouse.client_y(),
+			offset_x: mouse.offset_x(),
+			offset_y: mouse.offset_y(),
+			button: mouse.button(),
+			buttons: mouse.buttons(),
+			pointer_id: mouse
+				.dyn_ref::<web_sys::PointerEvent>()
+				.map(web_sys::PointerEvent::pointer_id),
+			modifiers: Modifiers {
+				shift: mouse.shift_key(),
+				ctrl: mouse.ctrl_key(),
+				alt: mouse.alt_key(),
+				meta: mouse.meta_key(),
+			},
+		}
+	}
+}
+
+#[derive(Debug, PartialEq)]
 pub(crate) struct Event {
-	pub(crate) target: web_sys::HtmlElement,
-	pub(crate) target_id: Id,
+	pub(crate) target: web_sys::Element,
+	/// The IDs of the target and its ancestors, from the target up to (but not including) the root.
+	///
+	/// Elements in the DOM without IDs, i.e., not created by `domi`, are skipped.
+	pub(crate) path: Vec<Id>,
 	pub(crate) kind: EventKind,
+	pub(crate) payload: EventPayload,
+	/// The event only propagates to elements at depths in `path` below this limit.
+	pub(crate) propagation_limit: Cell<usize>,
+	/// Set if the app asked to prevent the default action of the event while reacting to it.
+	pub(crate) default_prevented: Cell<bool>,
+	/// Set if the app reacted to the event, so the DOM may need to change.
+	pub(crate) handled: Cell<bool>,
 }
 
 impl Event {
-	pub(crate) fn from_dom(dom: &web_sys::Event) -> Option<Self> {
+	/// `ancestors` are the IDs of the elements that contain `root` in the virtual DOM, from its parent up, such as for a portal.
+	pub(crate) fn from_dom(
+		dom: &web_sys::Event,
+		root: &web_sys::Node,
+		ancestors: &[Id],
+	) -> Option<Self> {
 		let kind = EventKind::from_dom(&dom.type_())?;
 		let target = dom
 			.target()
 			.unwrap()
-			.dyn_into::<web_sys::HtmlElement>()
+			.dyn_into::<web_sys::Element>()
 			.unwrap();
-		let target_id = target.dataset().get(Id::DATA_KEY)?.parse().unwrap();
+
+		let mut path = Vec::new();
+		let mut current = Some(target.clone());
+		while let Some(element) = current {
+			let node: &web_sys::Node = &element;
+			if node == root {
+				break;
+			}
+			// read as attributes rather than through the dataset, which not every kind of element has
+			if let Some(id) = element.get_attribute(&dataset_attr(Id::DATA_KEY)) {
+				path.push(Id(id.parse().unwrap()));
+			}
+			// this has to happen now rather than after reacting to the event, in case the app panics
+			if let Some(prevented) = element.get_attribute(&dataset_attr(PREVENT_DEFAULT_DATA_KEY)) {
+				if prevented.parse::<EventSet>().unwrap().contains(kind) {
+					dom.prevent_default();
+				}
+			}
+			current = element.parent_element();
+		}
+		path.extend_from_slice(ancestors);
+		if path.is_empty() {
+			return None;
+		}
+
 		Some(Self {
 			target,
-			target_id: Id(target_id),
+			path,
 			kind,
+			payload: EventPayload::from_dom(dom),
+			propagation_limit: Cell::new(usize::MAX),
+			default_prevented: Cell::new(false),
+			handled: Cell::new(false),
 		})
 	}
+
+	/// Whether the event reaches the element at `depth` in `path`, considering whether it bubbles and whether propagation was stopped.
+	pub(crate) fn propagates_to(&self, depth: usize) -> bool {
+		depth < self.propagation_limit.get() && (depth == 0 || self.kind.bubbles())
+	}
+
+	/// Prevent the event from propagating beyond the element at `depth` in `path`.
+	pub(crate) fn stop_propagation_at(&self, depth: usize) {
+		let limit = self.propagation_limit.get().min(depth + 1);
+		self.propagation_limit.set(limit);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{EventKind, EventSet};
+
+	#[test]
+	fn kinds_round_trip() {
+		let mut all = EventSet::default();
+		for &kind in EventKind::ALL {
+			assert_eq!(EventKind::from_dom(kind.name()), Some(kind));
+			assert!(!all.contains(kind));
+			all = all.with(kind);
+		}
+		assert!(all.iter().eq(EventKind::ALL.iter().copied()));
+		assert_eq!(all.to_string().parse(), Ok(all));
+	}
 }
@@ -0,0 +1,100 @@
+use wasm_bindgen::JsCast as _;
+use web_sys::{HtmlElement, HtmlInputElement, HtmlTextAreaElement};
+
+use crate::id::Id;
+
+/// The selected text in an `<input>` or `<textarea>`, or the caret if nothing is selected.
+struct Selection {
+	start: u32,
+	end: u32,
+	direction: Option<String>,
+}
+
+impl Selection {
+	fn of(element: &HtmlElement) -> Option<Self> {
+		// not every kind of `<input>` has a selection, in which case these fail or return `None`
+		if let Some(input) = element.dyn_ref::<HtmlInputElement>() {
+			Some(Self {
+				start: input.selection_start().ok()??,
+				end: input.selection_end().ok()??,
+				direction: input.selection_direction().ok().flatten(),
+			})
+		} else if let Some(textarea) = element.dyn_ref::<HtmlTextAreaElement>() {
+			Some(Self {
+				start: textarea.selection_start().ok()??,
+				end: textarea.selection_end().ok()??,
+				direction: textarea.selection_direction().ok().flatten(),
+			})
+		} else {
+			None
+		}
+	}
+
+	fn restore(&self, element: &HtmlElement) {
+		let direction = self.direction.as_deref().unwrap_or("none");
+		// failures are ignored since the selection is only a nicety
+		if let Some(input) = element.dyn_ref::<HtmlInputElement>() {
+			_ = input.set_selection_range_with_direction(self.start, self.end, direction);
+		} else if let Some(textarea) = element.dyn_ref::<HtmlTextAreaElement>() {
+			_ = textarea.set_selection_range_with_direction(self.start, self.end, direction);
+		}
+	}
+}
+
+/// The focused element within the root, recorded before patching so that focus can be restored if the element is replaced.
+pub(crate) struct Focus {
+	id: Id,
+	element: HtmlElement,
+	selection: Option<Selection>,
+}
+
+fn active_element() -> Option<web_sys::Element> {
+	web_sys::window()?.document()?.active_element()
+}
+
+/// Find the element with the ID `id` within `root`.
+fn find(root: &web_sys::Element, id: Id) -> Option<HtmlElement> {
+	let selector = format!("[data-{}=\"{id}\"]", Id::DATA_KEY);
+	root
+		.query_selector(&selector)
+		.ok()??
+		.dyn_into::<HtmlElement>()
+		.ok()
+}
+
+impl Focus {
+	pub(crate) fn record(root: &web_sys::Element) -> Option<Self> {
+		let element = active_element()?.dyn_into::<HtmlElement>().ok()?;
+		if !root.contains(Some(&element)) {
+			return None;
+		}
+		let id = Id(element.dataset().get(Id::DATA_KEY)?.parse().ok()?);
+		let selection = Selection::of(&element);
+		Some(Self {
+			id,
+			element,
+			selection,
+		})
+	}
+
+	/// If the element lost focus because it was replaced, focus the element with the same ID and restore its selection.
+	pub(crate) fn restore(self, root: &web_sys::Element) {
+		let element: &web_sys::Element = &self.element;
+		if active_element().as_ref() == Some(element) {
+			return;
+		}
+		let Some(replacement) = find(root, self.id) else { return; };
+		if replacement.focus().is_ok() {
+			if let Some(selection) = &self.selection {
+				selection.restore(&replacement);
+			}
+		}
+	}
+}
+
+/// Focus the element with the ID `id` within `root`, if there is one.
+pub(crate) fn focus(root: &web_sys::Element, id: Id) {
+	if let Some(element) = find(root, id) {
+		_ = element.focus();
+	}
+}
@@ -1,6 +1,7 @@
-use std::hash::{Hash, Hasher};
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasherDefault, Hash, Hasher};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct Id(pub u64);
 
 impl Id {
@@ -13,7 +14,7 @@ impl std::fmt::Display for Id {
 	}
 }
 
-fn hash(source: &impl Hash) -> u64 {
+pub(crate) fn hash(source: &impl Hash) -> u64 {
 	let mut hasher = rustc_hash::FxHasher::default();
 	source.hash(&mut hasher);
 	hasher.finish()
@@ -54,3 +55,9 @@ impl Hasher for PassthroughHasher {
 		self.0 = v;
 	}
 }
+
+/// A `HashMap` keyed by [`Id`], using [`PassthroughHasher`].
+pub(crate) type IdMap<V> = HashMap<Id, V, BuildHasherDefault<PassthroughHasher>>;
+
+/// A `HashSet` of [`Id`]s, using [`PassthroughHasher`].
+pub(crate) type IdSet = HashSet<Id, BuildHasherDefault<PassthroughHasher>>;
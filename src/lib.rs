@@ -20,25 +20,94 @@
 #![allow(clippy::let_underscore_drop)]
 #![deny(unsafe_code)]
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
 use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast as _;
 use web_sys::HtmlElement;
 
-use self::event::Event;
+use self::backend::WebDom;
+use self::event::{Event, EventSet};
+pub use self::event::{EventKind, KeyboardInfo, Modifiers, PointerInfo, WheelInfo};
+use self::focus::Focus;
+use self::id::{Id, IdMap, IdSet};
+pub use self::node_ref::NodeRef;
+use self::scheduler::{AnimationFrameScheduler, Scheduler};
+use self::state::States;
+use self::updates::Updates;
 pub use self::vdom::{DomBuilder, ElementBuilder};
-use crate::event::HANDLED_EVENTS;
 
+pub mod backend;
 mod event;
+mod focus;
 mod id;
+mod node_ref;
 #[cfg(feature = "promise")]
 pub mod promise;
+pub mod scheduler;
+mod state;
+mod updates;
 pub mod vdom;
 
 type RenderCallback = Box<dyn FnMut(DomBuilder<'_, '_>)>;
 
+/// How the app reacts to DOM events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EventMode {
+	/// Render once to react to the event, without building the DOM, then again to build the DOM.
+	///
+	/// This way, the DOM always reflects the changes made in reaction to the event, even if they affect elements built before the one that received the event.
+	#[default]
+	TwoPass,
+	/// React to the event while building the DOM, in a single render.
+	///
+	/// This halves the work done for each event, but elements built before the one that received the event won't reflect changes made in reaction to it.
+	/// To fix that, an update is requested after each event that the app reacts to, so the view may be up to a frame late.
+	SinglePass,
+}
+
+/// Options that control how the app runs, for [`run_with`] and [`hydrate_with`].
+pub struct Options {
+	scheduler: Box<dyn Scheduler>,
+	event_mode: EventMode,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			scheduler: Box::new(AnimationFrameScheduler),
+			event_mode: EventMode::default(),
+		}
+	}
+}
+
+impl Options {
+	/// The default options, which are used by [`run`] and [`hydrate`].
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Use `scheduler` to decide when updates requested with [`Context::request_update`] happen.
+	///
+	/// The default is [`AnimationFrameScheduler`].
+	#[must_use]
+	pub fn scheduler(mut self, scheduler: impl Scheduler + 'static) -> Self {
+		self.scheduler = Box::new(scheduler);
+		self
+	}
+
+	/// Choose how the app reacts to DOM events.
+	///
+	/// The default is [`EventMode::TwoPass`].
+	#[must_use]
+	pub fn event_mode(mut self, event_mode: EventMode) -> Self {
+		self.event_mode = event_mode;
+		self
+	}
+}
+
 #[derive(Default)]
 struct VDoms {
 	last: vdom::VNodes,
@@ -55,7 +124,17 @@ impl VDoms {
 struct Inner {
 	// held only for ownership; never used
 	event_handler: Option<Closure<dyn Fn(web_sys::Event)>>,
+	/// The kinds of events that the app has checked for, so should be listened for.
+	wanted_events: EventSet,
+	/// The kinds of events that `event_handler` is currently registered for.
+	listening_events: EventSet,
 	vdoms: VDoms,
+	/// If set, the next DOM build adopts the existing contents of `root` rather than patching against `vdoms.last`.
+	hydrate: bool,
+	/// The unmount hooks of the elements currently in the DOM, by ID.
+	unmount_hooks: IdMap<Vec<vdom::Hook>>,
+	/// The portals currently in the DOM, by ID.
+	portals: IdMap<vdom::PortalMount<web_sys::Node>>,
 
 	root: HtmlElement,
 	render: RenderCallback,
@@ -65,48 +144,232 @@ struct Inner {
 enum DrawMode<'a> {
 	ReactToEvent(&'a Event),
 	BuildDom,
+	/// React to the event and build the DOM at the same time, for [`EventMode::SinglePass`].
+	ReactAndBuildDom(&'a Event),
+}
+
+impl DrawMode<'_> {
+	fn builds_dom(self) -> bool {
+		matches!(self, Self::BuildDom | Self::ReactAndBuildDom(..))
+	}
 }
 
 impl Inner {
+	/// Run the hooks registered in the last render for the elements that were mounted, updated, or unmounted.
+	///
+	/// `kept` contains the elements within memoized elements, whose hooks were not registered again because they were not rebuilt.
+	fn run_hooks(
+		&mut self,
+		mut hooks: IdMap<vdom::Hooks>,
+		notifications: &[vdom::Notification<web_sys::Node>],
+		kept: &IdSet,
+	) {
+		let mut unmount_hooks: IdMap<Vec<vdom::Hook>> = hooks
+			.iter_mut()
+			.map(|(&id, hooks)| (id, std::mem::take(&mut hooks.unmount)))
+			.filter(|(_, unmount)| !unmount.is_empty())
+			.collect();
+
+		// unmount hooks are removed as they run, so elements cannot be unmounted twice
+		for notification in notifications {
+			if notification.lifecycle != vdom::Lifecycle::Unmount {
+				continue;
+			}
+			let Some(element) = notification.node.dyn_ref::<web_sys::Element>() else { continue; };
+			for hook in self
+				.unmount_hooks
+				.remove(&notification.id)
+				.unwrap_or_default()
+			{
+				hook(element);
+			}
+		}
+
+		for notification in notifications {
+			let Some(element) = notification.node.dyn_ref::<web_sys::Element>() else { continue; };
+			let Some(hooks) = hooks.get_mut(&notification.id) else { continue; };
+			let to_run = match notification.lifecycle {
+				vdom::Lifecycle::Mount => std::mem::take(&mut hooks.mount),
+				vdom::Lifecycle::Update => std::mem::take(&mut hooks.update),
+				vdom::Lifecycle::Unmount => Vec::new(),
+			};
+			for hook in to_run {
+				hook(element);
+			}
+		}
+
+		for (id, hooks) in self.unmount_hooks.drain() {
+			if kept.contains(&id) {
+				unmount_hooks.entry(id).or_insert(hooks);
+			}
+		}
+		self.unmount_hooks = unmount_hooks;
+	}
+
 	fn new(root: HtmlElement, render: RenderCallback) -> Self {
 		Self {
 			event_handler: None,
+			wanted_events: EventSet::default(),
+			listening_events: EventSet::default(),
 			vdoms: VDoms::default(),
+			hydrate: false,
+			unmount_hooks: IdMap::default(),
+			portals: IdMap::default(),
 			root,
 			render,
 		}
 	}
 
+	/// The elements that the DOM is built in and events are listened for on: the root and the containers of the portals.
+	fn roots(&self) -> impl Iterator<Item = &web_sys::Element> {
+		let root: &web_sys::Element = &self.root;
+		let containers = self
+			.portals
+			.values()
+			.map(|portal| portal.container.unchecked_ref());
+		std::iter::once(root).chain(containers)
+	}
+
+	/// Convert `dom_event`, which was received by one of the [`roots`](Self::roots).
+	fn event_from_dom(&self, dom_event: &web_sys::Event) -> Option<Event> {
+		let current_target = dom_event.current_target()?;
+		let current_target = current_target.dyn_ref::<web_sys::Node>();
+		match self
+			.portals
+			.values()
+			.find(|portal| portal.container.is_same_node(current_target))
+		{
+			Some(portal) => Event::from_dom(
+				dom_event,
+				portal.container.unchecked_ref(),
+				&portal.ancestors,
+			),
+			None => Event::from_dom(dom_event, &self.root, &[]),
+		}
+	}
+
+	/// Patch the children of the portals into their containers, after the root has been patched.
+	fn patch_portals(&mut self) -> Vec<vdom::Notification<web_sys::Node>> {
+		let mut containers = Vec::new();
+		let notifications = vdom::patch_portals(
+			&WebDom,
+			&mut self.portals,
+			self.vdoms.last.children(),
+			self.vdoms.current.children(),
+			|container| containers.push(container.clone()),
+		);
+		// new containers are listened on like the root
+		for container in containers {
+			self.listen(container.unchecked_ref(), self.listening_events);
+		}
+		notifications
+	}
+
+	/// Listen for the events of the kinds `kinds` on `root`.
+	///
+	/// Listeners capture rather than waiting for events to bubble so that events that don't bubble, like `focus`, are received too.
+	fn listen(&self, root: &web_sys::Element, kinds: EventSet) {
+		let Some(event_handler) = &self.event_handler else { return; };
+		let event_handler = event_handler.as_ref().unchecked_ref::<js_sys::Function>();
+		for kind in kinds.iter() {
+			root
+				.add_event_listener_with_callback_and_bool(kind.name(), event_handler, true)
+				.unwrap();
+		}
+	}
+
 	fn draw(&mut self, mode: DrawMode<'_>, backing: &Context) {
+		let state = vdom::DrawState {
+			memos: if mode.builds_dom() {
+				vdom::memos(self.vdoms.last.children())
+			} else {
+				IdMap::default()
+			},
+			..vdom::DrawState::default()
+		};
 		self.vdoms.current.with_children_mut(|current_vdom| {
 			let builder = match mode {
-				DrawMode::ReactToEvent(event) => DomBuilder::new(None, Some(event), backing),
-				DrawMode::BuildDom => DomBuilder::new(Some(current_vdom), None, backing),
+				DrawMode::ReactToEvent(event) => DomBuilder::new(None, Some(event), Some(backing), &state),
+				DrawMode::BuildDom => DomBuilder::new(Some(current_vdom), None, Some(backing), &state),
+				DrawMode::ReactAndBuildDom(event) => {
+					DomBuilder::new(Some(current_vdom), Some(event), Some(backing), &state)
+				}
 			};
 			(self.render)(builder);
 		});
+		self.wanted_events = self.wanted_events.union(state.wanted_events.get());
+		let hooks = state.hooks.take();
+		// the memos borrow the last vdom, which the portals are patched from along with `self`
+		drop(state);
+
+		if mode.builds_dom() {
+			let focus = self
+				.roots()
+				.find_map(|root| Some((root.clone(), Focus::record(root)?)));
+			let mut notifications = if std::mem::take(&mut self.hydrate) {
+				vdom::hydrate(&WebDom, &self.root, self.vdoms.current.children())
+			} else {
+				vdom::patch(
+					&WebDom,
+					&self.root,
+					self.vdoms.last.children(),
+					self.vdoms.current.children(),
+				)
+			};
+			notifications.extend(self.patch_portals());
 
-		if let DrawMode::BuildDom = mode {
-			vdom::patch(
-				&self.root,
-				self.vdoms.last.children(),
-				self.vdoms.current.children(),
-			);
+			match backing.0.focus_request.take() {
+				Some(id) => {
+					for root in self.roots() {
+						focus::focus(root, id);
+					}
+				}
+				None => {
+					if let Some((root, focus)) = focus {
+						focus.restore(&root);
+					}
+				}
+			}
+
+			let kept = vdom::memo_reused_ids(self.vdoms.current.children());
+			backing.0.states.borrow_mut().collect_garbage(&kept);
+			self.run_hooks(hooks, &notifications, &kept);
 
 			self.vdoms.advance();
 		}
 	}
 }
 
+/// The state behind a [`Context`].
+///
+/// Everything other than `inner` is accessible while the render closure is running, so that the methods of [`Context`] can be called from it.
+struct Backing {
+	inner: RefCell<Inner>,
+	scheduler: Box<dyn Scheduler>,
+	event_mode: EventMode,
+	states: RefCell<States>,
+	/// The element to focus after the next patch, as requested by [`ElementBuilder::request_focus`].
+	focus_request: Cell<Option<Id>>,
+	/// Drawing borrows `inner`, so what is requested while drawing is deferred until after.
+	updates: Updates,
+}
+
 /// Your handle to `domi`.
 ///
 /// Allows you to control the execution of the app.
 #[derive(Clone)]
-pub struct Context(Rc<RefCell<Inner>>);
+pub struct Context(Rc<Backing>);
 
 impl Context {
-	fn new(root: HtmlElement, render: RenderCallback) -> Self {
-		let ret = Self(Rc::new(RefCell::new(Inner::new(root, render))));
+	fn new(root: HtmlElement, render: RenderCallback, options: Options) -> Self {
+		let ret = Self(Rc::new(Backing {
+			inner: RefCell::new(Inner::new(root, render)),
+			scheduler: options.scheduler,
+			event_mode: options.event_mode,
+			states: RefCell::default(),
+			focus_request: Cell::new(None),
+			updates: Updates::default(),
+		}));
 		ret.register_js_event_handlers();
 		ret
 	}
@@ -117,39 +380,50 @@ impl Context {
 			move |event| context.js_event_handler(&event)
 		};
 		let event_handler = Closure::<dyn Fn(web_sys::Event)>::new(event_handler);
-		let event_handler_js = event_handler.as_ref().unchecked_ref::<js_sys::Function>();
 
-		let mut inner = self.0.borrow_mut();
-		for (name, _) in HANDLED_EVENTS {
-			inner
-				.root
-				.add_event_listener_with_callback(name, event_handler_js)
-				.unwrap();
-		}
-		let replaced = inner.event_handler.replace(event_handler);
+		let replaced = self
+			.0
+			.inner
+			.borrow_mut()
+			.event_handler
+			.replace(event_handler);
 		debug_assert!(
 			replaced.is_none(),
 			"an event handler was already registered"
 		);
 	}
 
+	/// Start listening for the kinds of events that the app has started checking for.
+	fn listen_for_wanted_events(&self) {
+		let mut inner = self.0.inner.borrow_mut();
+		let new_events = inner.wanted_events.difference(inner.listening_events);
+		for root in inner.roots() {
+			inner.listen(root, new_events);
+		}
+		inner.listening_events = inner.wanted_events;
+	}
+
 	fn unregister_js_event_handlers(&self) {
-		let mut inner = self.0.borrow_mut();
+		let mut inner = self.0.inner.borrow_mut();
 		let event_handler = inner
 			.event_handler
 			.take()
 			.expect("no event handler was registered");
 		let event_handler = event_handler.as_ref().unchecked_ref::<js_sys::Function>();
-		for (name, _) in HANDLED_EVENTS {
-			inner
-				.root
-				.remove_event_listener_with_callback(name, event_handler)
-				.unwrap();
+		let listening_events = std::mem::take(&mut inner.listening_events);
+		for root in inner.roots() {
+			for kind in listening_events.iter() {
+				root
+					.remove_event_listener_with_callback_and_bool(kind.name(), event_handler, true)
+					.unwrap();
+			}
 		}
 	}
 
 	fn draw(&self, mode: DrawMode<'_>) {
-		self.0.borrow_mut().draw(mode, self);
+		updates::draw(self, mode.builds_dom(), || {
+			self.0.inner.borrow_mut().draw(mode, self);
+		});
 	}
 
 	fn react_to_event(&self, event: &Event) {
@@ -160,26 +434,107 @@ impl Context {
 		self.draw(DrawMode::BuildDom);
 	}
 
-	fn js_event_handler(&self, event: &web_sys::Event) {
-		if let Some(event) = Event::from_dom(event) {
-			self.react_to_event(&event);
-			// show any view changes due to events handled in the previous `draw` call
-			self.build_dom();
+	pub(crate) fn state<T: 'static>(
+		&self,
+		id: Id,
+		owner: Option<Id>,
+		init: impl FnOnce() -> T,
+	) -> Rc<RefCell<T>> {
+		let existing = self.0.states.borrow_mut().get(id);
+		existing.unwrap_or_else(|| {
+			// `init` may request other states, so the states must not be borrowed while it runs
+			let value = Rc::new(RefCell::new(init()));
+			self
+				.0
+				.states
+				.borrow_mut()
+				.insert(id, owner, Rc::clone(&value));
+			value
+		})
+	}
+
+	pub(crate) fn request_focus(&self, id: Id) {
+		self.0.focus_request.set(Some(id));
+	}
+
+	fn js_event_handler(&self, dom_event: &web_sys::Event) {
+		// events that occur while drawing are caused by the app itself, such as `blur` when the focused element is removed, so are ignored
+		if self.0.updates.is_drawing() {
+			return;
+		}
+
+		let event = self.0.inner.borrow().event_from_dom(dom_event);
+		let Some(event) = event else { return; };
+		match self.0.event_mode {
+			EventMode::TwoPass => {
+				self.react_to_event(&event);
+				if event.default_prevented.get() {
+					dom_event.prevent_default();
+				}
+				// show any view changes due to events handled in the previous `draw` call, and honor focus requests made in it
+				if event.handled.get() || self.0.focus_request.get().is_some() {
+					self.build_dom();
+				}
+			}
+			EventMode::SinglePass => {
+				self.draw(DrawMode::ReactAndBuildDom(&event));
+				if event.default_prevented.get() {
+					dom_event.prevent_default();
+				}
+				// elements built before the event was handled may be out of date
+				if event.handled.get() {
+					self.request_update();
+				}
+			}
 		}
 	}
 
-	/// Tell the app to update immediately.
+	/// Tell the app to update soon, as decided by the [`Scheduler`], which by default is before the next frame.
 	///
-	/// Do not call this function while inside the `render` closure.
-	/// Rather, register event handlers which call this function asynchronously.
+	/// Requests are coalesced, so requesting many updates at once, such as when several [`Promise`](crate::promise::Promise)s complete, only causes one update.
 	///
-	/// As WASM is single-threaded, this will just update the app directly.
+	/// If this is called while inside the `render` closure, the update is scheduled to happen after the current render.
+	/// Calling it on every render will therefore cause the app to render on every frame.
 	pub fn request_update(&self) {
+		updates::request_update(self);
+	}
+
+	/// Tell the app to update immediately, rather than waiting for the [`Scheduler`] like [`request_update`](Self::request_update).
+	///
+	/// As WASM is single-threaded, this will just update the app directly.
+	/// If this is called while inside the `render` closure, the update happens as soon as the current render finishes.
+	/// Take care not to do so on every render, as the app would never stop rendering.
+	pub fn request_update_now(&self) {
 		self.build_dom();
 	}
 
 	/// Stop the app from rendering and reacting to events.
+	///
+	/// If this is called while inside the `render` closure, the app stops once the current render finishes.
+	/// Stopping an app that has already stopped has no effect.
 	pub fn stop(&self) {
+		updates::stop(self);
+	}
+}
+
+impl updates::App for Context {
+	fn updates(&self) -> &Updates {
+		&self.0.updates
+	}
+
+	fn scheduler(&self) -> &dyn Scheduler {
+		&*self.0.scheduler
+	}
+
+	fn build_dom(&self) {
+		Context::build_dom(self);
+	}
+
+	fn drawn(&self) {
+		self.listen_for_wanted_events();
+	}
+
+	fn stopped(&self) {
 		self.unregister_js_event_handlers();
 	}
 }
@@ -188,10 +543,54 @@ impl Context {
 ///
 /// This function returns after setting up the app, rather than blocking while running the UI. Subsequent updates occur through DOM event handlers.
 pub fn run<F: FnMut(DomBuilder<'_, '_>) + 'static>(root: HtmlElement, render: F) {
-	run_(root, Box::new(render));
+	run_with(root, Options::default(), render);
 }
 
-fn run_(root: HtmlElement, render: Box<dyn FnMut(DomBuilder<'_, '_>)>) {
-	let context = Context::new(root, render);
+/// Like [`run`], but with custom [`Options`].
+pub fn run_with<F: FnMut(DomBuilder<'_, '_>) + 'static>(
+	root: HtmlElement,
+	options: Options,
+	render: F,
+) {
+	run_(root, Box::new(render), options, false);
+}
+
+/// Like [`run`], but reuse the HTML already inside `root` instead of rendering from scratch.
+///
+/// This is intended for HTML produced by [`render_to_string`] with the same render code.
+/// Existing nodes are checked against the first render and adopted if they match; subtrees that don't match are rebuilt.
+pub fn hydrate<F: FnMut(DomBuilder<'_, '_>) + 'static>(root: HtmlElement, render: F) {
+	hydrate_with(root, Options::default(), render);
+}
+
+/// Like [`hydrate`], but with custom [`Options`].
+pub fn hydrate_with<F: FnMut(DomBuilder<'_, '_>) + 'static>(
+	root: HtmlElement,
+	options: Options,
+	render: F,
+) {
+	run_(root, Box::new(render), options, true);
+}
+
+fn run_(
+	root: HtmlElement,
+	render: Box<dyn FnMut(DomBuilder<'_, '_>)>,
+	options: Options,
+	hydrate: bool,
+) {
+	let context = Context::new(root, render, options);
+	context.0.inner.borrow_mut().hydrate = hydrate;
 	context.build_dom();
 }
+
+/// Render the app once to a string of HTML, without a running app.
+///
+/// This allows the same render code used with [`run`] to produce static HTML, for example on the server.
+/// As there is no running app, no events will be received, and [`DomBuilder::context`] must not be called.
+#[must_use]
+pub fn render_to_string(render: impl FnOnce(DomBuilder<'_, '_>)) -> String {
+	let mut vdom = vdom::VNodes::default();
+	let state = vdom::DrawState::default();
+	vdom.with_children_mut(|children| render(DomBuilder::new(Some(children), None, None, &state)));
+	vdom::to_html(vdom.children())
+}
@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::JsCast;
+
+/// A reference to the element in the DOM that corresponds to an element built with [`ElementBuilder`](crate::ElementBuilder).
+///
+/// Create one outside the render closure, bind it with [`ElementBuilder::node_ref`](crate::ElementBuilder::node_ref), and use it whenever the actual element is needed, such as to focus it or to draw on a canvas.
+/// Clones refer to the same element.
+#[derive(Debug, Clone, Default)]
+pub struct NodeRef(Rc<RefCell<Option<web_sys::Element>>>);
+
+impl NodeRef {
+	/// Create a reference that doesn't refer to any element yet.
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Get the element, if it is in the DOM.
+	///
+	/// The element is filled in once the DOM has been patched, so it is not available during the render in which it is first bound.
+	#[must_use]
+	pub fn get(&self) -> Option<web_sys::Element> {
+		self.0.borrow().clone()
+	}
+
+	/// Get the element as a more specific type, such as [`HtmlElement`](web_sys::HtmlElement), if it is in the DOM and has that type.
+	#[must_use]
+	pub fn cast<T: JsCast>(&self) -> Option<T> {
+		self.get()?.dyn_into().ok()
+	}
+
+	pub(crate) fn set(&self, element: &web_sys::Element) {
+		*self.0.borrow_mut() = Some(element.clone());
+	}
+
+	/// Stop referring to `element`, unless the reference was already moved to another element.
+	pub(crate) fn clear(&self, element: &web_sys::Element) {
+		let mut current = self.0.borrow_mut();
+		if current.as_ref() == Some(element) {
+			*current = None;
+		}
+	}
+}
@@ -0,0 +1,80 @@
+//! Provides the [`Scheduler`] abstraction that decides when requested updates actually happen.
+#![allow(clippy::module_name_repetitions)]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast as _;
+
+type Callback = Box<dyn FnOnce()>;
+
+/// Decides when updates requested with [`Context::request_update`](crate::Context::request_update) are performed.
+///
+/// Requests are coalesced, so at most one callback is scheduled at a time.
+pub trait Scheduler {
+	/// Call `callback` once, at some later point.
+	fn schedule(&self, callback: Callback);
+}
+
+/// The default [`Scheduler`], which performs updates just before the browser renders the next frame, using [`requestAnimationFrame`](https://developer.mozilla.org/en-US/docs/Web/API/window/requestAnimationFrame).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnimationFrameScheduler;
+
+impl Scheduler for AnimationFrameScheduler {
+	fn schedule(&self, callback: Callback) {
+		let callback = Closure::once_into_js(move |_timestamp: f64| callback());
+		web_sys::window()
+			.unwrap()
+			.request_animation_frame(callback.unchecked_ref())
+			.unwrap();
+	}
+}
+
+/// A [`Scheduler`] which only performs updates when [`run_pending`](Self::run_pending) is called.
+///
+/// This is useful for controlling exactly when updates happen, such as in tests.
+/// Clones share the same queue, so keep a clone to run the callbacks after passing one to the app.
+#[derive(Clone, Default)]
+pub struct ManualScheduler {
+	pending: Rc<RefCell<Vec<Callback>>>,
+}
+
+impl ManualScheduler {
+	/// Create a scheduler with no pending callbacks.
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// The number of callbacks waiting to be run.
+	#[must_use]
+	pub fn pending(&self) -> usize {
+		self.pending.borrow().len()
+	}
+
+	/// Run the callbacks that are currently pending.
+	///
+	/// Callbacks scheduled while this runs are left pending until the next call.
+	pub fn run_pending(&self) {
+		let pending = std::mem::take(&mut *self.pending.borrow_mut());
+		for callback in pending {
+			callback();
+		}
+	}
+}
+
+impl Scheduler for ManualScheduler {
+	fn schedule(&self, callback: Callback) {
+		self.pending.borrow_mut().push(callback);
+	}
+}
+
+impl std::fmt::Debug for ManualScheduler {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		formatter
+			.debug_struct("ManualScheduler")
+			.field("pending", &self.pending())
+			.finish()
+	}
+}
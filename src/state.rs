@@ -0,0 +1,100 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::id::{Id, IdMap, IdSet};
+
+struct Entry {
+	value: Rc<dyn Any>,
+	/// The element whose children requested the state, or `None` for the root.
+	owner: Option<Id>,
+	/// Set if the state was requested since the last collection.
+	used: bool,
+}
+
+/// The local state requested with [`DomBuilder::state`](crate::DomBuilder::state), by ID.
+#[derive(Default)]
+pub(crate) struct States(IdMap<Entry>);
+
+impl States {
+	/// Get the state with the ID `id`, if it exists and has the type `T`.
+	pub(crate) fn get<T: 'static>(&mut self, id: Id) -> Option<Rc<RefCell<T>>> {
+		let entry = self.0.get_mut(&id)?;
+		let value = Rc::clone(&entry.value).downcast::<RefCell<T>>().ok()?;
+		entry.used = true;
+		Some(value)
+	}
+
+	/// Insert the state with the ID `id`, replacing any existing state.
+	pub(crate) fn insert<T: 'static>(&mut self, id: Id, owner: Option<Id>, value: Rc<RefCell<T>>) {
+		let entry = Entry {
+			value,
+			owner,
+			used: true,
+		};
+		self.0.insert(id, entry);
+	}
+
+	/// Drop the states that were not requested since the last collection, unless they are owned by an element in `kept`.
+	pub(crate) fn collect_garbage(&mut self, kept: &IdSet) {
+		self.0.retain(|_, entry| {
+			let keep = entry.used || matches!(entry.owner, Some(owner) if kept.contains(&owner));
+			entry.used = false;
+			keep
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	use super::States;
+	use crate::id::{Id, IdSet};
+
+	#[test]
+	fn unused_states_are_dropped() {
+		let id = Id::new("state");
+		let mut states = States::default();
+		states.insert(id, None, Rc::new(RefCell::new(1)));
+
+		// inserting counts as requesting
+		states.collect_garbage(&IdSet::default());
+		assert!(states.get::<i32>(id).is_some());
+		states.collect_garbage(&IdSet::default());
+
+		states.collect_garbage(&IdSet::default());
+		assert!(states.get::<i32>(id).is_none());
+	}
+
+	#[test]
+	fn states_of_kept_elements_are_retained() {
+		let (id, owner) = (Id::new("state"), Id::new("owner"));
+		let mut states = States::default();
+		states.insert(id, Some(owner), Rc::new(RefCell::new(1)));
+		states.collect_garbage(&IdSet::default());
+
+		let kept: IdSet = [owner].into_iter().collect();
+		states.collect_garbage(&kept);
+		states.collect_garbage(&kept);
+		assert_eq!(*states.get::<i32>(id).unwrap().borrow(), 1);
+
+		// but only while the owner is kept
+		states.collect_garbage(&IdSet::default());
+		states.collect_garbage(&IdSet::default());
+		assert!(states.get::<i32>(id).is_none());
+	}
+
+	#[test]
+	fn other_types_replace_the_state() {
+		let id = Id::new("state");
+		let mut states = States::default();
+		states.insert(id, None, Rc::new(RefCell::new(1)));
+		assert!(states.get::<String>(id).is_none());
+
+		states.insert(id, None, Rc::new(RefCell::new(String::from("a"))));
+		assert!(states.get::<i32>(id).is_none());
+		assert_eq!(*states.get::<String>(id).unwrap().borrow(), "a");
+	}
+}
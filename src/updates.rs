@@ -0,0 +1,277 @@
+use std::cell::Cell;
+
+use crate::scheduler::Scheduler;
+
+/// What must happen after [`Updates::draw`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AfterDraw {
+	/// Nothing was drawn, as the app is stopped or was already drawing.
+	Skipped,
+	/// The app was stopped while drawing, so must stop now.
+	Stop,
+	/// The draw finished, and if `update` is set, an immediate update was requested while drawing, so the DOM must be built again.
+	Drawn { update: bool },
+}
+
+/// Decides when the app draws, deferring what is requested while it draws until the draw finishes.
+///
+/// This is kept apart from the DOM so that it can be tested outside of the browser.
+#[derive(Default)]
+pub(crate) struct Updates {
+	/// Set if an update has been scheduled and no DOM build has happened since.
+	update_scheduled: Cell<bool>,
+	/// Set while drawing.
+	drawing: Cell<bool>,
+	/// Set if an immediate update was requested while drawing, so must happen after it.
+	update_deferred: Cell<bool>,
+	/// Set if the app was stopped while drawing, so must stop after it.
+	stop_deferred: Cell<bool>,
+	stopped: Cell<bool>,
+}
+
+impl Updates {
+	pub(crate) fn is_drawing(&self) -> bool {
+		self.drawing.get()
+	}
+
+	/// Run `draw`, unless the app is stopped or already drawing, in which case an immediate update is deferred until the current draw finishes.
+	///
+	/// `builds_dom` is set if `draw` builds the DOM, which fulfills any scheduled update.
+	pub(crate) fn draw(&self, builds_dom: bool, draw: impl FnOnce()) -> AfterDraw {
+		if self.stopped.get() {
+			return AfterDraw::Skipped;
+		}
+		if self.drawing.replace(true) {
+			// `request_update_now` was called from the render closure
+			self.update_deferred.set(true);
+			return AfterDraw::Skipped;
+		}
+
+		if builds_dom {
+			self.update_scheduled.set(false);
+		}
+		draw();
+		self.drawing.set(false);
+
+		if self.stop_deferred.take() {
+			return AfterDraw::Stop;
+		}
+		AfterDraw::Drawn {
+			update: self.update_deferred.take(),
+		}
+	}
+
+	/// Note that an update was requested, returning `true` if it must be scheduled, or `false` if one already is or the app is stopped.
+	pub(crate) fn request_update(&self) -> bool {
+		!self.stopped.get() && !self.update_scheduled.replace(true)
+	}
+
+	/// Check if a scheduled update must still happen, as a DOM build since it was scheduled fulfills it.
+	pub(crate) fn scheduled_update_due(&self) -> bool {
+		self.update_scheduled.get()
+	}
+
+	/// Stop the app, returning `true` if it must stop now, or `false` if it is drawing, so stops after the draw, or has already stopped.
+	pub(crate) fn stop(&self) -> bool {
+		if self.drawing.get() {
+			self.stop_deferred.set(true);
+			return false;
+		}
+		if self.stopped.replace(true) {
+			return false;
+		}
+		self.update_scheduled.set(false);
+		true
+	}
+}
+
+/// An app whose updates are decided by [`Updates`], which is [`Context`](crate::Context), or a stand-in for it in tests.
+pub(crate) trait App: Clone + 'static {
+	fn updates(&self) -> &Updates;
+	fn scheduler(&self) -> &dyn Scheduler;
+	/// Build the DOM, which must go through [`draw`].
+	fn build_dom(&self);
+	/// Called after each draw, once the app can be used again.
+	fn drawn(&self);
+	/// Called once when the app stops.
+	fn stopped(&self);
+}
+
+/// Run `draw` as decided by [`Updates::draw`], then do what must happen after it.
+pub(crate) fn draw(app: &impl App, builds_dom: bool, draw: impl FnOnce()) {
+	match app.updates().draw(builds_dom, draw) {
+		AfterDraw::Skipped => {}
+		AfterDraw::Stop => stop(app),
+		AfterDraw::Drawn { update } => {
+			app.drawn();
+			if update {
+				app.build_dom();
+			}
+		}
+	}
+}
+
+/// Schedule an update of `app`, unless one already is or the app is stopped.
+pub(crate) fn request_update(app: &impl App) {
+	if !app.updates().request_update() {
+		return;
+	}
+
+	let scheduled = app.clone();
+	app.scheduler().schedule(Box::new(move || {
+		if scheduled.updates().scheduled_update_due() {
+			scheduled.build_dom();
+		}
+	}));
+}
+
+/// Stop `app`, unless it is drawing, in which case it stops after the draw.
+pub(crate) fn stop(app: &impl App) {
+	if app.updates().stop() {
+		app.stopped();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+	use std::rc::Rc;
+
+	use super::{App, Updates};
+	use crate::scheduler::{ManualScheduler, Scheduler};
+
+	type Render = Box<dyn Fn(&TestApp, u32)>;
+
+	struct Inner {
+		updates: Updates,
+		scheduler: ManualScheduler,
+		builds: Cell<u32>,
+		/// The number of times the app was actually stopped.
+		stops: Cell<u32>,
+		/// Run on each build, with the number of the build, starting at 1.
+		render: Render,
+	}
+
+	/// Stands in for [`Context`](crate::Context), counting DOM builds instead of building a DOM.
+	#[derive(Clone)]
+	struct TestApp(Rc<Inner>);
+
+	impl TestApp {
+		fn new(render: impl Fn(&TestApp, u32) + 'static) -> Self {
+			Self(Rc::new(Inner {
+				updates: Updates::default(),
+				scheduler: ManualScheduler::new(),
+				builds: Cell::new(0),
+				stops: Cell::new(0),
+				render: Box::new(render),
+			}))
+		}
+
+		fn builds(&self) -> u32 {
+			self.0.builds.get()
+		}
+
+		fn stops(&self) -> u32 {
+			self.0.stops.get()
+		}
+
+		fn request_update_now(&self) {
+			self.build_dom();
+		}
+
+		fn request_update(&self) {
+			super::request_update(self);
+		}
+
+		fn stop(&self) {
+			super::stop(self);
+		}
+	}
+
+	impl App for TestApp {
+		fn updates(&self) -> &Updates {
+			&self.0.updates
+		}
+
+		fn scheduler(&self) -> &dyn Scheduler {
+			&self.0.scheduler
+		}
+
+		fn build_dom(&self) {
+			super::draw(self, true, || {
+				self.0.builds.set(self.builds() + 1);
+				(self.0.render)(self, self.builds());
+			});
+		}
+
+		fn drawn(&self) {}
+
+		fn stopped(&self) {
+			self.0.stops.set(self.stops() + 1);
+		}
+	}
+
+	#[test]
+	fn request_update_now_in_render() {
+		let app = TestApp::new(|app, build| {
+			if build == 1 {
+				app.request_update_now();
+				// deferred until the current build finishes
+				assert_eq!(app.builds(), 1);
+			}
+		});
+		app.request_update_now();
+		assert_eq!(app.builds(), 2);
+	}
+
+	#[test]
+	fn stop_in_render() {
+		let app = TestApp::new(|app, _| {
+			app.stop();
+			app.stop();
+			app.request_update_now();
+			assert_eq!(app.stops(), 0);
+		});
+		app.request_update_now();
+		assert_eq!(app.builds(), 1);
+		assert_eq!(app.stops(), 1);
+
+		app.request_update_now();
+		app.request_update();
+		assert_eq!(app.0.scheduler.pending(), 0);
+		assert_eq!(app.builds(), 1);
+	}
+
+	#[test]
+	fn stop_twice() {
+		let app = TestApp::new(|_, _| {});
+		app.request_update_now();
+		app.request_update();
+		app.stop();
+		app.stop();
+		assert_eq!(app.stops(), 1);
+
+		// the update scheduled before stopping doesn't happen
+		app.0.scheduler.run_pending();
+		assert_eq!(app.builds(), 1);
+	}
+
+	#[test]
+	fn requests_are_coalesced() {
+		let app = TestApp::new(|_, _| {});
+		for _ in 0..10 {
+			app.request_update();
+		}
+		assert_eq!(app.0.scheduler.pending(), 1);
+		assert_eq!(app.builds(), 0);
+
+		app.0.scheduler.run_pending();
+		assert_eq!(app.builds(), 1);
+
+		// a build before the scheduled update fulfills it
+		app.request_update();
+		app.request_update_now();
+		app.0.scheduler.run_pending();
+		assert_eq!(app.builds(), 2);
+	}
+}
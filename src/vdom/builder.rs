@@ -1,38 +1,70 @@
 #![allow(clippy::module_name_repetitions)]
 
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
+use std::rc::Rc;
 
 use bumpalo::collections::Vec as BVec;
 use wasm_bindgen::JsCast as _;
 
-use crate::event::{Event, EventKind};
-use crate::id::Id;
-use crate::vdom::{VNode, VNodeElement};
-use crate::Context;
+use crate::backend::PropertyValue;
+use crate::event::{Event, EventKind, EventPayload, EventSet};
+use crate::id::{Id, IdMap};
+use crate::vdom::{
+	PortalTarget, VNode, VNodeElement, VNodeFragment, VNodePortal, VNodeRawHtml, HTML_NAMESPACE,
+	MATHML_NAMESPACE, SVG_NAMESPACE,
+};
+use crate::{Context, NodeRef};
+use crate::{KeyboardInfo, PointerInfo, WheelInfo};
+
+/// A lifecycle hook, as registered with [`ElementBuilder::on_mount`] and similar.
+pub(crate) type Hook = Box<dyn FnOnce(&web_sys::Element)>;
+
+/// The lifecycle hooks registered for an element.
+#[derive(Default)]
+pub(crate) struct Hooks {
+	pub(crate) mount: Vec<Hook>,
+	pub(crate) update: Vec<Hook>,
+	pub(crate) unmount: Vec<Hook>,
+}
+
+/// Information shared with and gathered from the builders over the course of one render.
+#[derive(Default)]
+pub(crate) struct DrawState<'l> {
+	/// The kinds of events that the app checked for.
+	pub(crate) wanted_events: Cell<EventSet>,
+	/// The memoized elements of the last render, by ID.
+	pub(crate) memos: IdMap<&'l VNodeElement<'l>>,
+	/// The lifecycle hooks registered for elements, by ID.
+	pub(crate) hooks: RefCell<IdMap<Hooks>>,
+}
 
 #[derive(Clone, Copy)]
 struct Shared<'a> {
 	event: Option<&'a Event>,
-	context: &'a Context,
+	context: Option<&'a Context>,
+	state: &'a DrawState<'a>,
 }
 
 enum ElementOrId<'a, 'x> {
 	Element(&'a mut VNodeElement<'x>),
+	/// The element of a [`VNodeRawHtml`], whose children are not built, as its HTML is its content.
+	RawHtml(&'a mut VNodeElement<'x>),
 	Id(Id),
 }
 
 impl<'x> ElementOrId<'_, 'x> {
 	fn as_element(&mut self) -> Option<&mut VNodeElement<'x>> {
 		match self {
-			Self::Element(element) => Some(element),
+			Self::Element(element) | Self::RawHtml(element) => Some(element),
 			Self::Id(..) => None,
 		}
 	}
 
 	fn id(&self) -> Id {
 		match self {
-			Self::Element(element) => element.id,
+			Self::Element(element) | Self::RawHtml(element) => element.id,
 			Self::Id(id) => *id,
 		}
 	}
@@ -74,46 +106,362 @@ impl<'x> ElementBuilder<'_, 'x> {
 		self
 	}
 
+	/// Set a property of the inline style of the element, such as `color`, replacing the old value if one was present.
+	///
+	/// Unlike setting the `style` attribute, this only updates the properties that changed.
+	/// Don't combine this with the `style` attribute.
+	pub fn style(&mut self, prop: &'static str, value: impl AsRef<str>) -> &mut Self {
+		if let Some(vdom) = self.vdom.as_element() {
+			let value = vdom.children.bump().alloc_str(value.as_ref());
+			vdom.styles.insert(prop, value);
+		}
+		self
+	}
+
+	/// Add the class `name` to the element.
+	///
+	/// Unlike setting the `class` attribute, classes are added and removed individually, so classes added by other code, such as animation libraries, are left alone.
+	/// Don't combine this with the `class` attribute.
+	/// Like that attribute, `name` can contain several classes separated by whitespace, and is ignored if it is empty.
+	pub fn class(&mut self, name: impl AsRef<str>) -> &mut Self {
+		if let Some(vdom) = self.vdom.as_element() {
+			let bump = vdom.children.bump();
+			for name in name.as_ref().split_ascii_whitespace() {
+				vdom.classes.insert(bump.alloc_str(name));
+			}
+		}
+		self
+	}
+
+	/// Add the class `name` to the element if `condition` is set.
+	///
+	/// See [`class`](Self::class) for details.
+	pub fn class_if(&mut self, name: impl AsRef<str>, condition: bool) -> &mut Self {
+		if condition {
+			self.class(name);
+		}
+		self
+	}
+
+	/// Set a DOM property of the element, as opposed to an attribute, replacing the old value if one was present.
+	///
+	/// Properties are suitable for state that the user can change, such as the value of an `<input>`.
+	/// A property is only set when the value provided here changes, so what the user entered since is kept until then.
+	pub fn prop(&mut self, name: &'static str, value: PropertyValue<'_>) -> &mut Self {
+		if let Some(vdom) = self.vdom.as_element() {
+			let value = value.copy_into(vdom.children.bump());
+			vdom.properties.insert(name, value);
+		}
+		self
+	}
+
+	/// Set the `value` property of the element, for `<input>`, `<select>`, and `<textarea>` elements.
+	///
+	/// See [`prop`](Self::prop) for details.
+	pub fn prop_value(&mut self, value: impl AsRef<str>) -> &mut Self {
+		self.prop("value", PropertyValue::String(value.as_ref()))
+	}
+
+	/// Set the `checked` property of the element, for checkboxes and radio buttons.
+	///
+	/// See [`prop`](Self::prop) for details.
+	pub fn prop_checked(&mut self, checked: bool) -> &mut Self {
+		self.prop("checked", PropertyValue::Bool(checked))
+	}
+
+	/// Set the `selected` property of the element, for `<option>` elements.
+	///
+	/// See [`prop`](Self::prop) for details.
+	pub fn prop_selected(&mut self, selected: bool) -> &mut Self {
+		self.prop("selected", PropertyValue::Bool(selected))
+	}
+
 	/// Get a [`DomBuilder`] for the children of this element.
 	///
 	/// This method is the second part of the [`DomBuilder`]-[`ElementBuilder`] cycle.
 	pub fn children(&mut self) -> DomBuilder<'_, 'x> {
+		let parent_id = Some(self.vdom.id());
+		let raw_html = matches!(self.vdom, ElementOrId::RawHtml(..));
+		let element = self.vdom.as_element();
+		// the content of `<foreignObject>` is HTML again
+		let namespace = element
+			.as_ref()
+			.and_then(|element| element.namespace.filter(|_| element.tag != "foreignObject"));
 		DomBuilder {
-			parent_id: Some(self.vdom.id()),
-			vdom: self.vdom.as_element().map(|element| &mut element.children),
+			parent_id,
+			namespace,
+			// the children of raw HTML are built like when only reacting to an event, so nothing is added to the virtual DOM
+			vdom: element
+				.filter(|_| !raw_html)
+				.map(|element| &mut element.children),
 			shared: self.shared,
 		}
 	}
 
-	/// Check if the element was clicked.
-	#[must_use]
-	pub fn clicked(&self) -> bool {
+	/// Put the element in the namespace `namespace`, such as [`SVG_NAMESPACE`](crate::vdom::SVG_NAMESPACE), which its children inherit.
+	///
+	/// Elements are usually in the correct namespace without calling this: `<svg>` and `<math>` are in the SVG and `MathML` namespaces, and other elements are in the namespace of their parent.
+	/// Call this before [`children`](Self::children) so the children inherit the namespace.
+	pub fn namespace(&mut self, namespace: &'static str) -> &mut Self {
+		if let Some(vdom) = self.vdom.as_element() {
+			vdom.namespace = (namespace != HTML_NAMESPACE).then_some(namespace);
+		}
 		self
-			.shared
-			.event
-			.filter(|event| event.target_id == self.vdom.id() && event.kind == EventKind::Click)
-			.is_some()
 	}
 
-	/// Check if the element was clicked.
+	/// Check if the element received an event of the kind `kind`.
+	///
+	/// Events that bubble are also received by the ancestors of their target, unless an element in between calls [`stop_propagation`](Self::stop_propagation).
+	///
+	/// Prefer the methods for specific kinds of events, such as [`clicked`](Self::clicked), where they exist.
 	#[must_use]
-	pub fn changed(&self) -> bool {
+	pub fn received(&self, kind: EventKind) -> bool {
+		self.want(kind);
+		match self.propagated_event() {
+			Some(event) if event.kind == kind => {
+				event.handled.set(true);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// Check if the element or any of its descendants was the target of an event of the kind `kind`.
+	///
+	/// Unlike [`received`](Self::received), this applies to events that don't bubble and ignores [`stop_propagation`](Self::stop_propagation).
+	#[must_use]
+	pub fn received_within(&self, kind: EventKind) -> bool {
+		self.want(kind);
+		match self.event_depth() {
+			Some((event, _)) if event.kind == kind => {
+				event.handled.set(true);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// Check if the element or any of its descendants was clicked, even if propagation was stopped.
+	///
+	/// This is equivalent to calling [`received_within`](Self::received_within) with [`EventKind::Click`].
+	#[must_use]
+	pub fn clicked_within(&self) -> bool {
+		self.received_within(EventKind::Click)
+	}
+
+	/// Stop the current event from propagating from this element to its ancestors.
+	///
+	/// This only affects ancestors that check for the event after this is called.
+	/// Usually that is the case, since elements are typically checked for events after their children are built.
+	pub fn stop_propagation(&mut self) -> &mut Self {
+		if let Some((event, depth)) = self.event_depth() {
+			event.stop_propagation_at(depth);
+		}
 		self
-			.shared
-			.event
-			.filter(|event| event.target_id == self.vdom.id() && event.kind == EventKind::Change)
-			.is_some()
+	}
+
+	/// Prevent the default action of the current event, such as following a link when it is clicked.
+	///
+	/// This only has an effect if the event reached this element.
+	/// Since it takes effect while the app is reacting to the event, it must be called in the same render as the event is checked for; consider [`prevent_default_on`](Self::prevent_default_on) instead.
+	pub fn prevent_default(&mut self) -> &mut Self {
+		if let Some((event, _)) = self.event_depth() {
+			event.default_prevented.set(true);
+		}
+		self
+	}
+
+	/// Always prevent the default action of events of the kind `kind` that reach this element, such as [`EventKind::Submit`] for a form.
+	///
+	/// Unlike [`prevent_default`](Self::prevent_default), this is declared as part of the DOM, so it applies before the app reacts to the event.
+	pub fn prevent_default_on(&mut self, kind: EventKind) -> &mut Self {
+		// the event must be listened for in order to prevent it
+		self.want(kind);
+		if let Some(vdom) = self.vdom.as_element() {
+			vdom.prevent_default = vdom.prevent_default.with(kind);
+		}
+		self
+	}
+
+	/// Run `hook` with the element after it is added to the DOM.
+	///
+	/// Hooks run once the DOM has been patched, so the element is in its final position.
+	/// This is useful for focusing or measuring the element, or for handing it to a library that manages it.
+	pub fn on_mount(&mut self, hook: impl FnOnce(&web_sys::Element) + 'static) -> &mut Self {
+		self.hook(|hooks| &mut hooks.mount, Box::new(hook))
+	}
+
+	/// Run `hook` with the element after it is patched, if it was already in the DOM.
+	///
+	/// See [`on_mount`](Self::on_mount) for details.
+	pub fn on_update(&mut self, hook: impl FnOnce(&web_sys::Element) + 'static) -> &mut Self {
+		self.hook(|hooks| &mut hooks.update, Box::new(hook))
+	}
+
+	/// Run `hook` with the element after it is removed from the DOM.
+	///
+	/// As the element is no longer rendered when it is removed, the hook provided in the last render in which the element was present is the one that runs.
+	/// See [`on_mount`](Self::on_mount) for details.
+	pub fn on_unmount(&mut self, hook: impl FnOnce(&web_sys::Element) + 'static) -> &mut Self {
+		self.hook(|hooks| &mut hooks.unmount, Box::new(hook))
+	}
+
+	fn hook(&mut self, list: impl FnOnce(&mut Hooks) -> &mut Vec<Hook>, hook: Hook) -> &mut Self {
+		if let Some(vdom) = self.vdom.as_element() {
+			vdom.tracked = true;
+			let mut hooks = self.shared.state.hooks.borrow_mut();
+			list(hooks.entry(vdom.id).or_default()).push(hook);
+		}
+		self
+	}
+
+	/// Focus the element once the DOM is next patched.
+	///
+	/// This focuses the element every time it is called, so only call it when focus should move, such as in reaction to an event.
+	/// Calls in the same render override each other.
+	pub fn request_focus(&mut self) -> &mut Self {
+		if let Some(context) = self.shared.context {
+			context.request_focus(self.vdom.id());
+		}
+		self
+	}
+
+	/// Bind `node_ref` to the element, so that it refers to the element in the DOM after each patch, until the element is removed.
+	pub fn node_ref(&mut self, node_ref: &NodeRef) -> &mut Self {
+		let mounted = node_ref.clone();
+		self.on_mount(move |element| mounted.set(element));
+		let updated = node_ref.clone();
+		self.on_update(move |element| updated.set(element));
+		let unmounted = node_ref.clone();
+		self.on_unmount(move |element| unmounted.clear(element))
+	}
+
+	fn want(&self, kind: EventKind) {
+		let wanted_events = &self.shared.state.wanted_events;
+		wanted_events.set(wanted_events.get().with(kind));
+	}
+
+	/// Get the current event and the depth of this element in its path, if this element is the target or an ancestor of the target.
+	fn event_depth(&self) -> Option<(&Event, usize)> {
+		let event = self.shared.event?;
+		let depth = event.path.iter().position(|&id| id == self.vdom.id())?;
+		Some((event, depth))
+	}
+
+	/// Get the current event if it propagated to this element.
+	fn propagated_event(&self) -> Option<&Event> {
+		self
+			.event_depth()
+			.filter(|&(event, depth)| event.propagates_to(depth))
+			.map(|(event, _)| event)
+	}
+
+	/// Get information about the keyboard event that the element received, if any.
+	///
+	/// Events are only listened for once they have been checked for, so use this along with a method such as [`key_pressed`](Self::key_pressed).
+	/// Like those methods, this marks the event as handled if there is one, so the DOM is rebuilt.
+	#[must_use]
+	pub fn keyboard(&self) -> Option<KeyboardInfo> {
+		let event = self.propagated_event()?;
+		let EventPayload::Keyboard(info) = &event.payload else { return None; };
+		event.handled.set(true);
+		Some(info.clone())
+	}
+
+	/// Get information about the mouse or pointer event that the element received, if any.
+	///
+	/// Events are only listened for once they have been checked for, so use this along with a method such as [`pointer_pressed`](Self::pointer_pressed).
+	/// Like those methods, this marks the event as handled if there is one, so the DOM is rebuilt.
+	#[must_use]
+	pub fn pointer(&self) -> Option<PointerInfo> {
+		let event = self.propagated_event()?;
+		let info = match &event.payload {
+			EventPayload::Pointer(info) => *info,
+			EventPayload::Wheel(info) => info.pointer,
+			_ => return None,
+		};
+		event.handled.set(true);
+		Some(info)
+	}
+
+	/// Get information about the wheel event that the element received, if any.
+	///
+	/// Events are only listened for once they have been checked for, so use this along with [`wheeled`](Self::wheeled).
+	/// Like that method, this marks the event as handled if there is one, so the DOM is rebuilt.
+	#[must_use]
+	pub fn wheel(&self) -> Option<WheelInfo> {
+		let event = self.propagated_event()?;
+		let EventPayload::Wheel(info) = &event.payload else { return None; };
+		event.handled.set(true);
+		Some(*info)
 	}
 
 	/// Get the target of the event in the DOM, if an event occurred.
 	///
+	/// This marks the event as handled, so the DOM is rebuilt.
 	/// This method is generally only used when implementing higher-level constructs.
 	#[must_use]
-	pub fn event_target(&self) -> Option<web_sys::HtmlElement> {
-		self.shared.event.map(|event| event.target.clone())
+	pub fn event_target(&self) -> Option<web_sys::Element> {
+		let event = self.shared.event?;
+		event.handled.set(true);
+		Some(event.target.clone())
 	}
 }
 
+macro_rules! event_methods {
+	($($(#[$extra_meta:meta])* $vis:vis fn $method_name:ident() => $kind:ident;)*) => {
+		impl ElementBuilder<'_, '_> {
+			$(
+				$(#[$extra_meta])*
+				///
+				/// This is equivalent to calling [`received`](Self::received) with
+				#[doc = concat!("[`EventKind::", stringify!($kind), "`].")]
+				#[must_use]
+				$vis fn $method_name(&self) -> bool {
+					self.received(EventKind::$kind)
+				}
+			)*
+		}
+	};
+}
+
+event_methods! {
+	/// Check if the element was clicked.
+	pub fn clicked() => Click;
+	/// Check if the element was double-clicked.
+	pub fn double_clicked() => DoubleClick;
+	/// Check if the value of the element was changed and committed by the user.
+	pub fn changed() => Change;
+	/// Check if the value of the element was changed, such as by typing a character.
+	pub fn input() => Input;
+	/// Check if the element, a form, was submitted.
+	pub fn submitted() => Submit;
+	/// Check if a key was pressed while the element was focused.
+	pub fn key_pressed() => KeyDown;
+	/// Check if a key was released while the element was focused.
+	pub fn key_released() => KeyUp;
+	/// Check if the element gained focus.
+	pub fn focused() => Focus;
+	/// Check if the element lost focus.
+	pub fn blurred() => Blur;
+	/// Check if the mouse moved onto the element.
+	pub fn hovered() => MouseOver;
+	/// Check if the mouse moved off of the element.
+	pub fn unhovered() => MouseOut;
+	/// Check if a pointer was pressed on the element.
+	pub fn pointer_pressed() => PointerDown;
+	/// Check if a pointer was released on the element.
+	pub fn pointer_released() => PointerUp;
+	/// Check if a pointer moved over the element.
+	pub fn pointer_moved() => PointerMove;
+	/// Check if the wheel was scrolled over the element.
+	pub fn wheeled() => Wheel;
+	/// Check if the user asked for the context menu of the element, such as by right-clicking it.
+	pub fn context_menu_requested() => ContextMenu;
+	/// Check if the content of the element was scrolled.
+	pub fn scrolled() => Scroll;
+}
+
 macro_rules! attr_methods {
 	($($(#[$extra_meta:meta])* $vis:vis fn $method_name:ident(attr: $attr_kind:tt, value: $value_kind:tt);)*) => {
 		impl<'x> ElementBuilder<'_, 'x> {
@@ -182,21 +530,31 @@ attr_methods! {
 /// The main type used to build the DOM in the `run` callback.
 pub struct DomBuilder<'a, 'x> {
 	parent_id: Option<Id>,
+	/// The namespace that child elements inherit, or `None` for HTML.
+	namespace: Option<&'static str>,
 	vdom: Option<&'a mut BVec<'x, VNode<'x>>>,
 	shared: Shared<'a>,
 }
 
 impl<'a, 'x> DomBuilder<'a, 'x> {
 	/// If `None` is provided for `vdom`, then don't actually build a DOM, but still process events.
+	///
+	/// If `None` is provided for `context`, the DOM is being built without a running app, e.g., for [`render_to_string`](crate::render_to_string).
 	pub(crate) fn new(
 		vdom: Option<&'a mut BVec<'x, VNode<'x>>>,
 		event: Option<&'a Event>,
-		context: &'a Context,
+		context: Option<&'a Context>,
+		state: &'a DrawState<'a>,
 	) -> Self {
 		Self {
 			parent_id: None,
+			namespace: None,
 			vdom,
-			shared: Shared { event, context },
+			shared: Shared {
+				event,
+				context,
+				state,
+			},
 		}
 	}
 
@@ -208,17 +566,132 @@ impl<'a, 'x> DomBuilder<'a, 'x> {
 		}
 	}
 
-	fn element_(&mut self, id: Id, tag: &str) -> ElementBuilder<'_, 'x> {
+	/// Add a comment node with the provided `content`.
+	///
+	/// A comment is invisible, so it is useful as a placeholder for content that is absent, which keeps its siblings in the same positions rather than shifting them.
+	pub fn comment(&mut self, content: impl AsRef<str>) {
+		if let Some(vdom) = &mut self.vdom {
+			let bump = vdom.bump();
+			vdom.push(VNode::Comment(bump.alloc_str(content.as_ref())));
+		}
+	}
+
+	/// Add a group of siblings with the provided `id`, returning a builder for them.
+	///
+	/// The siblings are added directly to the current element, without a wrapper element, but their IDs only need to be unique within the group.
+	/// This allows a helper to add several siblings without risking collisions with those the caller adds.
+	pub fn fragment(&mut self, id: impl Hash) -> DomBuilder<'_, 'x> {
+		let id = self.child_id(id);
+		let vdom = self.vdom.as_deref_mut().map(|vdom| {
+			let idx = vdom.len();
+			vdom.push(VNode::Fragment(VNodeFragment {
+				id,
+				children: BVec::new_in(vdom.bump()),
+			}));
+			let VNode::Fragment(fragment) = &mut vdom[idx] else { unreachable!() };
+			&mut fragment.children
+		});
+		DomBuilder {
+			parent_id: Some(id),
+			namespace: self.namespace,
+			vdom,
+			shared: self.shared,
+		}
+	}
+
+	/// Add a group of elements with the provided `id` that is rendered into `target` instead of the current element, as built by `build`.
+	///
+	/// This allows content such as modals and tooltips to escape ancestors that would clip it, such as those with `overflow: hidden`.
+	/// The elements are put in a container added to `target`, so `target` may have other children, but it must be outside the root of the app.
+	/// The containers of portals with the same target are in the same order as the portals.
+	/// Events within the portal propagate to the ancestors of the portal, not those of `target`, as if the elements were in place of the portal.
+	///
+	/// Portals only take effect in a running app; [`render_to_string`](crate::render_to_string) leaves them out.
+	pub fn portal(
+		&mut self,
+		id: impl Hash,
+		target: &web_sys::Element,
+		build: impl FnOnce(DomBuilder<'_, 'x>),
+	) {
+		let target: &web_sys::Node = target;
+		self.portal_in(id, PortalTarget::new(target.clone()), build);
+	}
+
+	/// Like [`portal`](Self::portal), with a target for any backend.
+	pub(crate) fn portal_in(
+		&mut self,
+		id: impl Hash,
+		target: PortalTarget,
+		build: impl FnOnce(DomBuilder<'_, 'x>),
+	) {
+		let id = self.child_id(id);
+		let vdom = self.vdom.as_deref_mut().map(|vdom| {
+			let idx = vdom.len();
+			vdom.push(VNode::Portal(VNodePortal {
+				id,
+				target,
+				children: BVec::new_in(vdom.bump()),
+			}));
+			let VNode::Portal(portal) = &mut vdom[idx] else { unreachable!() };
+			&mut portal.children
+		});
+		build(DomBuilder {
+			parent_id: Some(id),
+			namespace: None,
+			vdom,
+			shared: self.shared,
+		});
+	}
+
+	/// Add a child element like [`element`](Self::element), with `html` as is for its content, which is replaced whenever `html` changes.
+	///
+	/// Unlike [`text`](Self::text), this doesn't escape `html`, so it must be trusted, such as the output of a Markdown converter on content the app controls.
+	/// Otherwise, it is open to cross-site scripting.
+	/// The element can be configured like any other, except that nothing built with its [`children`](ElementBuilder::children) is added, as `html` is its content.
+	pub fn raw_html(
+		&mut self,
+		id: impl Hash,
+		tag: impl AsRef<str>,
+		html: impl AsRef<str>,
+	) -> ElementBuilder<'_, 'x> {
+		self.element_(self.child_id(id), tag.as_ref(), Some(html.as_ref()))
+	}
+
+	/// Add an element, which is raw HTML if `raw_html` is provided.
+	fn element_(&mut self, id: Id, tag: &str, raw_html: Option<&str>) -> ElementBuilder<'_, 'x> {
 		let inner = if let Some(vdom) = &mut self.vdom {
 			let idx = vdom.len();
-			vdom.push(VNode::Element(VNodeElement {
+			let namespace = match tag {
+				"svg" => Some(SVG_NAMESPACE),
+				"math" => Some(MATHML_NAMESPACE),
+				_ => self.namespace,
+			};
+			let element = VNodeElement {
 				id,
 				tag: vdom.bump().alloc_str(tag),
+				namespace,
 				attributes: HashMap::new(),
+				styles: HashMap::new(),
+				classes: HashSet::new(),
+				properties: HashMap::new(),
+				prevent_default: EventSet::default(),
+				memo_key: None,
+				memo_reused: false,
+				tracked: false,
 				children: BVec::new_in(vdom.bump()),
-			}));
-			let VNode::Element(element) = &mut vdom[idx] else { unreachable!() };
-			ElementOrId::Element(element)
+			};
+			vdom.push(match raw_html {
+				Some(html) => VNode::RawHtml(VNodeRawHtml {
+					element,
+					html: vdom.bump().alloc_str(html),
+				}),
+				None => VNode::Element(element),
+			});
+			match &mut vdom[idx] {
+				VNode::Element(element) => ElementOrId::Element(element),
+				VNode::RawHtml(VNodeRawHtml { element, .. }) => ElementOrId::RawHtml(element),
+				_ => unreachable!(),
+			}
 		} else {
 			ElementOrId::Id(id)
 		};
@@ -235,33 +708,286 @@ impl<'a, 'x> DomBuilder<'a, 'x> {
 	/// See the documentation in the crate root for more information about IDs.
 	#[inline]
 	pub fn element(&mut self, id: impl Hash, tag: impl AsRef<str>) -> ElementBuilder<'_, 'x> {
-		self.element_(
-			self
-				.parent_id
-				.map_or_else(|| Id::new(&id), |parent_id| parent_id.with(&id)),
-			tag.as_ref(),
-		)
+		self.element_(self.child_id(id), tag.as_ref(), None)
+	}
+
+	fn child_id(&self, id: impl Hash) -> Id {
+		self
+			.parent_id
+			.map_or_else(|| Id::new(&id), |parent_id| parent_id.with(&id))
+	}
+
+	/// Get the local state with the provided `id`, initializing it with `init` if it doesn't exist yet.
+	///
+	/// Like those of elements, `id` must be unique among the states of the current element, but it may be the same as the ID of an element.
+	/// The state is retained across renders as long as it is requested in every render, and is dropped otherwise.
+	/// If it was last requested with a different type, it is replaced.
+	///
+	/// When there is no running app, as with [`render_to_string`](crate::render_to_string), the state is not retained.
+	#[must_use]
+	pub fn state<T: 'static>(&self, id: impl Hash, init: impl FnOnce() -> T) -> Rc<RefCell<T>> {
+		let id = self.child_id(id);
+		match self.shared.context {
+			Some(context) => context.state(id, self.parent_id, init),
+			None => Rc::new(RefCell::new(init())),
+		}
+	}
+
+	/// Add a child element like [`element`](Self::element), whose children are only built by `build` if `key` changed since the last render.
+	///
+	/// Otherwise, the children from the last render are reused and the DOM within the element is left untouched, which saves time for large sections that rarely change.
+	/// As such, `key` must capture everything that the children depend on.
+	/// `build` is always run when reacting to an event that happened within the element, so that the event is received.
+	#[inline]
+	pub fn memo(
+		&mut self,
+		id: impl Hash,
+		tag: impl AsRef<str>,
+		key: impl Hash,
+		build: impl FnOnce(DomBuilder<'_, 'x>),
+	) -> ElementBuilder<'_, 'x> {
+		let key = crate::id::hash(&key);
+		let shared = self.shared;
+		let mut ret = self.element(id, tag);
+		let id = ret.vdom.id();
+		let event_within = matches!(shared.event, Some(event) if event.path.contains(&id));
+
+		let reused = match ret.vdom.as_element() {
+			Some(element) => {
+				element.memo_key = Some(key);
+				match shared.state.memos.get(&id) {
+					Some(old)
+						if old.memo_key == Some(key)
+							&& old.tag == element.tag
+							&& old.namespace == element.namespace
+							&& !event_within =>
+					{
+						let bump = element.children.bump();
+						element
+							.children
+							.extend(old.children.iter().map(|child| child.copy_into(bump)));
+						element.memo_reused = true;
+						true
+					}
+					_ => false,
+				}
+			}
+			// only reacting to an event, which can only be received by the children if it happened within the element
+			None => !event_within,
+		};
+		if !reused {
+			build(ret.children());
+		}
+		ret
 	}
 
 	/// Add a `<input type="text">` with the value `value`.
+	///
+	/// `value` is updated as the user types.
 	#[inline]
 	#[allow(clippy::missing_panics_doc)] // does not panic
 	pub fn text_input(&mut self, id: impl Hash, value: &mut String) -> ElementBuilder<'_, 'x> {
 		let event = self.shared.event;
 		let mut ret = self.element(id, "input");
-		ret.attr("value", &value);
-		if ret.changed() {
+		if ret.input() | ret.changed() {
 			if let Some(input) = event.unwrap().target.dyn_ref::<web_sys::HtmlInputElement>() {
 				*value = input.value();
 			}
 		}
+		ret.prop_value(&value);
+		ret
+	}
+
+	/// Add a `<textarea>` with the value `value`.
+	///
+	/// `value` is updated as the user types.
+	#[inline]
+	#[allow(clippy::missing_panics_doc)] // does not panic
+	pub fn textarea(&mut self, id: impl Hash, value: &mut String) -> ElementBuilder<'_, 'x> {
+		let event = self.shared.event;
+		let mut ret = self.element(id, "textarea");
+		if ret.input() | ret.changed() {
+			if let Some(textarea) = event
+				.unwrap()
+				.target
+				.dyn_ref::<web_sys::HtmlTextAreaElement>()
+			{
+				*value = textarea.value();
+			}
+		}
+		ret.prop_value(&value);
 		ret
 	}
 
+	/// Add a `<input type="checkbox">` which is checked if `checked` is set.
+	#[inline]
+	#[allow(clippy::missing_panics_doc)] // does not panic
+	pub fn checkbox(&mut self, id: impl Hash, checked: &mut bool) -> ElementBuilder<'_, 'x> {
+		let event = self.shared.event;
+		let mut ret = self.element(id, "input");
+		if ret.changed() {
+			if let Some(input) = event.unwrap().target.dyn_ref::<web_sys::HtmlInputElement>() {
+				*checked = input.checked();
+			}
+		}
+		ret.attr_static("type", "checkbox").prop_checked(*checked);
+		ret
+	}
+
+	/// Add a `<input type="number">` with the value `value`.
+	///
+	/// Fractional numbers are allowed; add a `step` attribute to the returned element to change that.
+	/// `value` is updated as the user types, but if they enter something that is not a number, it is left as it was.
+	#[inline]
+	#[allow(clippy::missing_panics_doc)] // does not panic
+	pub fn number_input(&mut self, id: impl Hash, value: &mut f64) -> ElementBuilder<'_, 'x> {
+		let event = self.shared.event;
+		let mut ret = self.element(id, "input");
+		if ret.input() | ret.changed() {
+			if let Some(input) = event.unwrap().target.dyn_ref::<web_sys::HtmlInputElement>() {
+				let new = input.value_as_number();
+				if !new.is_nan() {
+					*value = new;
+				}
+			}
+		}
+		ret
+			.attr_static("type", "number")
+			.attr_static("step", "any")
+			.prop_value(value.to_string());
+		ret
+	}
+
+	/// Add a `<input type="range">` with the value `value`, which the user can move between `min` and `max`.
+	///
+	/// Fractional numbers are allowed; add a `step` attribute to the returned element to change that.
+	#[inline]
+	#[allow(clippy::missing_panics_doc)] // does not panic
+	pub fn range(
+		&mut self,
+		id: impl Hash,
+		value: &mut f64,
+		min: f64,
+		max: f64,
+	) -> ElementBuilder<'_, 'x> {
+		let event = self.shared.event;
+		let mut ret = self.element(id, "input");
+		if ret.changed() {
+			if let Some(input) = event.unwrap().target.dyn_ref::<web_sys::HtmlInputElement>() {
+				let new = input.value_as_number();
+				if !new.is_nan() {
+					*value = new;
+				}
+			}
+		}
+		ret
+			.attr_static("type", "range")
+			.attr("min", min.to_string())
+			.attr("max", max.to_string())
+			.attr_static("step", "any")
+			.prop_value(value.to_string());
+		ret
+	}
+
+	/// Add a `<select>` with an `<option>` for each of `options`, which are pairs of a value and its label.
+	///
+	/// The option whose value equals `value` is selected, and selecting a different option sets `value` to its value.
+	#[inline]
+	#[allow(clippy::missing_panics_doc)] // does not panic
+	pub fn select<T: PartialEq + Clone>(
+		&mut self,
+		id: impl Hash,
+		value: &mut T,
+		options: &[(T, impl AsRef<str>)],
+	) -> ElementBuilder<'_, 'x> {
+		let event = self.shared.event;
+		let mut ret = self.element(id, "select");
+		if ret.changed() {
+			if let Some(select) = event
+				.unwrap()
+				.target
+				.dyn_ref::<web_sys::HtmlSelectElement>()
+			{
+				let selected = usize::try_from(select.selected_index()).ok();
+				if let Some((option, _)) = selected.and_then(|i| options.get(i)) {
+					*value = option.clone();
+				}
+			}
+		}
+		{
+			let mut children = ret.children();
+			for (i, (option, label)) in options.iter().enumerate() {
+				let mut element = children.element(i, "option");
+				element
+					.attr("value", i.to_string())
+					.prop_selected(option == value);
+				element.children().text(label);
+			}
+		}
+		ret
+	}
+
+	/// Add a group of `<input type="radio">`s, each within a `<label>`, for each of `options`, which are pairs of a value and its label.
+	///
+	/// The radio button whose value equals `value` is checked, and checking a different one sets `value` to its value.
+	/// The returned element is a `<div>` containing the labels.
+	#[inline]
+	#[allow(clippy::missing_panics_doc)] // does not panic
+	pub fn radio_group<T: PartialEq + Clone>(
+		&mut self,
+		id: impl Hash,
+		value: &mut T,
+		options: &[(T, impl AsRef<str>)],
+	) -> ElementBuilder<'_, 'x> {
+		let event = self.shared.event;
+		let mut ret = self.element(id, "div");
+		// the change bubbles from the radio button that was checked, whose value is its index
+		if ret.changed() {
+			if let Some(input) = event.unwrap().target.dyn_ref::<web_sys::HtmlInputElement>() {
+				let checked = input.value().parse::<usize>().ok();
+				if let Some((option, _)) = checked.and_then(|i| options.get(i)) {
+					*value = option.clone();
+				}
+			}
+		}
+		// radio buttons with the same name are grouped by the browser, e.g., for keyboard navigation
+		let name = ret.vdom.id().to_string();
+		let mut children = ret.children();
+		for (i, (option, label)) in options.iter().enumerate() {
+			let mut label_element = children.element(i, "label");
+			let mut label_children = label_element.children();
+			label_children
+				.element("input", "input")
+				.attr_static("type", "radio")
+				.attr("name", &name)
+				.attr("value", i.to_string())
+				.prop_checked(option == value);
+			label_children.text(label);
+		}
+		ret
+	}
+
+	/// Tell the app that its state changed in reaction to the current event, if any, so the DOM must be rebuilt.
+	///
+	/// After an event, the DOM is only rebuilt if an element received it, as determined by methods such as [`ElementBuilder::clicked`].
+	/// Call this if the state changed some other way, such as in reaction to an event that no element received.
+	pub fn mark_changed(&self) {
+		if let Some(event) = self.shared.event {
+			event.handled.set(true);
+		}
+	}
+
 	/// Get a reference to the containing [`Context`].
+	///
+	/// # Panics
+	///
+	/// Panics if there is no running app, which is the case when rendering with [`render_to_string`](crate::render_to_string).
 	#[inline]
 	#[must_use]
 	pub fn context(&self) -> &'a Context {
-		self.shared.context
+		self
+			.shared
+			.context
+			.expect("there is no `Context` when rendering to a string")
 	}
 }
@@ -0,0 +1,293 @@
+use super::{flatten, VNode, VNodeElement, VNodePortal};
+use crate::backend::PropertyValue;
+use crate::event::PREVENT_DEFAULT_DATA_KEY;
+use crate::id::Id;
+
+/// Elements that cannot have children and have no end tag.
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+	"area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+	"wbr",
+];
+
+/// Elements whose text content is not parsed, so must not be escaped.
+pub(crate) const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+pub(crate) fn escape(out: &mut String, text: &str) {
+	for ch in text.chars() {
+		match ch {
+			'&' => out.push_str("&amp;"),
+			'<' => out.push_str("&lt;"),
+			'>' => out.push_str("&gt;"),
+			'"' => out.push_str("&quot;"),
+			'\'' => out.push_str("&#39;"),
+			ch => out.push(ch),
+		}
+	}
+}
+
+/// The content of the comment written between adjacent text nodes, which the browser would otherwise merge into one when parsing.
+pub(super) const TEXT_SEPARATOR: &str = "";
+
+/// Write the text of the raw text element `tag` without escaping, except for anything that would end the element early.
+///
+/// `</` is written as `<\/` when followed by `tag`, which means the same in both JavaScript strings and CSS.
+fn write_raw_text(out: &mut String, text: &str, tag: &str) {
+	let mut rest = text;
+	while let Some(index) = rest.find("</") {
+		out.push_str(&rest[..=index]);
+		rest = &rest[index + 1..];
+		let name = &rest.as_bytes()[1..];
+		if name.len() >= tag.len() && name[..tag.len()].eq_ignore_ascii_case(tag.as_bytes()) {
+			out.push('\\');
+		}
+	}
+	out.push_str(rest);
+}
+
+fn write_attribute(out: &mut String, attr: &str, value: &str) {
+	out.push(' ');
+	out.push_str(attr);
+	out.push_str("=\"");
+	escape(out, value);
+	out.push('"');
+}
+
+impl VNodeElement<'_> {
+	/// Combine the `style` attribute, if any, with the style properties.
+	fn style_attribute(&self) -> String {
+		let mut styles: Vec<_> = self.styles.iter().collect();
+		styles.sort_unstable();
+
+		let mut ret = String::new();
+		if let Some(attribute) = self.attributes.get("style") {
+			ret.push_str(attribute.trim_end().trim_end_matches(';'));
+			ret.push_str("; ");
+		}
+		for (prop, value) in styles {
+			ret.push_str(prop);
+			ret.push_str(": ");
+			ret.push_str(value);
+			ret.push_str("; ");
+		}
+		ret.truncate(ret.trim_end().len());
+		ret
+	}
+
+	/// Combine the `class` attribute, if any, with the classes.
+	fn class_attribute(&self) -> String {
+		let mut classes: Vec<_> = self.classes.iter().copied().collect();
+		classes.sort_unstable();
+
+		let attribute = self.attributes.get("class").copied().unwrap_or_default();
+		let mut tokens: Vec<&str> = attribute.split_ascii_whitespace().collect();
+		for class in classes {
+			if !tokens.contains(&class) {
+				tokens.push(class);
+			}
+		}
+		tokens.join(" ")
+	}
+
+	fn write_html(&self, out: &mut String) {
+		self.write_html_with(out, |out, raw_text| {
+			write_children(out, &self.children, raw_text);
+		});
+	}
+
+	/// Write the element, with its content written by `write_content`, which is passed the tag if this is a raw text element.
+	fn write_html_with(
+		&self,
+		out: &mut String,
+		write_content: impl FnOnce(&mut String, Option<&str>),
+	) {
+		out.push('<');
+		out.push_str(self.tag);
+
+		write_attribute(out, &format!("data-{}", Id::DATA_KEY), &self.id.to_string());
+		if let Some(prevent_default) = self.prevent_default_data() {
+			write_attribute(
+				out,
+				&format!("data-{PREVENT_DEFAULT_DATA_KEY}"),
+				&prevent_default,
+			);
+		}
+		// sorted so the output is deterministic
+		let mut attributes: Vec<_> = self
+			.attributes
+			.iter()
+			.filter(|(&attr, _)| attr != "style" || self.styles.is_empty())
+			.filter(|(&attr, _)| attr != "class" || self.classes.is_empty())
+			.collect();
+		attributes.sort_unstable();
+		for (attr, value) in attributes {
+			write_attribute(out, attr, value);
+		}
+
+		if !self.styles.is_empty() {
+			write_attribute(out, "style", &self.style_attribute());
+		}
+		if !self.classes.is_empty() {
+			write_attribute(out, "class", &self.class_attribute());
+		}
+
+		// the initial value of a `<textarea>` is its content rather than an attribute
+		let textarea_value = match self.properties.get("value") {
+			Some(PropertyValue::String(value)) if self.tag == "textarea" => Some(*value),
+			_ => None,
+		};
+
+		// properties can't be represented in HTML, but the corresponding attributes determine their initial values
+		let mut properties: Vec<_> = self
+			.properties
+			.iter()
+			.filter(|(name, _)| !self.attributes.contains_key(*name))
+			.filter(|(name, _)| textarea_value.is_none() || **name != "value")
+			.collect();
+		properties.sort_unstable_by_key(|&(name, _)| name);
+		for (name, value) in properties {
+			match value {
+				PropertyValue::String(value) => write_attribute(out, name, value),
+				PropertyValue::Bool(true) => write_attribute(out, name, ""),
+				PropertyValue::Bool(false) => {}
+			}
+		}
+
+		out.push('>');
+
+		// in SVG and MathML, these rules don't apply, even to elements with the same tags
+		let html = self.namespace.is_none();
+
+		if html && VOID_ELEMENTS.contains(&self.tag) {
+			return;
+		}
+
+		if let Some(value) = textarea_value {
+			escape(out, value);
+		}
+
+		let raw_text = (html && RAW_TEXT_ELEMENTS.contains(&self.tag)).then_some(self.tag);
+		write_content(out, raw_text);
+
+		out.push_str("</");
+		out.push_str(self.tag);
+		out.push('>');
+	}
+}
+
+impl VNode<'_> {
+	/// `raw_text` is the tag of the raw text element these nodes are in, if any.
+	fn write_html(&self, out: &mut String, raw_text: Option<&str>) {
+		match self {
+			Self::Text(text) => match raw_text {
+				Some(tag) => write_raw_text(out, text, tag),
+				None => escape(out, text),
+			},
+			Self::Element(element) => element.write_html(out),
+			// trusted, so passed through without escaping
+			Self::RawHtml(raw) => raw
+				.element
+				.write_html_with(out, |out, _| out.push_str(raw.html)),
+			Self::Fragment(..) => unreachable!("fragments are flattened into their parent"),
+			Self::Comment(text) => {
+				// escaping a comment isn't standard, but it keeps the text from ending the comment early
+				out.push_str("<!--");
+				escape(out, text);
+				out.push_str("-->");
+			}
+			// the target of the portal is outside the HTML, so its children are only rendered once the app runs
+			Self::Portal(..) => {
+				out.push_str("<!--");
+				out.push_str(VNodePortal::PLACEHOLDER);
+				out.push_str("-->");
+			}
+		}
+	}
+}
+
+/// `raw_text` is the tag of the raw text element `nodes` are in, if any.
+fn write_children(out: &mut String, nodes: &[VNode<'_>], raw_text: Option<&str>) {
+	let mut after_text = false;
+	for node in flatten(nodes) {
+		let is_text = matches!(node, VNode::Text(..));
+		// raw text isn't parsed, so a comment would become part of the text instead
+		if after_text && is_text && raw_text.is_none() {
+			out.push_str("<!--");
+			out.push_str(TEXT_SEPARATOR);
+			out.push_str("-->");
+		}
+		after_text = is_text;
+		node.write_html(out, raw_text);
+	}
+}
+
+/// Serialize the nodes to HTML.
+pub(crate) fn to_html(nodes: &[VNode<'_>]) -> String {
+	let mut out = String::new();
+	write_children(&mut out, nodes, None);
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::render_to_string;
+
+	#[test]
+	fn escape_text_and_attributes() {
+		let html = render_to_string(|mut ui| {
+			ui.element("p", "p")
+				.attr("title", "\"quoted\"")
+				.children()
+				.text("<b> & </b>");
+		});
+		assert!(html.contains(r#" title="&quot;quoted&quot;""#));
+		assert!(html.contains(">&lt;b&gt; &amp; &lt;/b&gt;</p>"));
+	}
+
+	#[test]
+	fn separate_adjacent_text() {
+		let html = render_to_string(|mut ui| {
+			ui.text("a");
+			ui.fragment("f").text("b");
+			ui.comment("c");
+			ui.text("d");
+			ui.element("script", "script").children().text("1;");
+			ui.text("e");
+		});
+		assert!(html.starts_with("a<!---->b<!--c-->d<script"));
+		assert!(html.ends_with("</script>e"));
+
+		let html = render_to_string(|mut ui| {
+			let mut script = ui.element("script", "script");
+			let mut script = script.children();
+			script.text("1;");
+			script.text("2;");
+		});
+		assert!(html.ends_with(">1;2;</script>"));
+	}
+
+	#[test]
+	fn raw_html() {
+		let html = render_to_string(|mut ui| {
+			ui.raw_html("help", "section", "<b>trusted</b>")
+				.class("help");
+		});
+		assert!(html.starts_with("<section data-"));
+		assert!(html.ends_with(r#" class="help"><b>trusted</b></section>"#));
+	}
+
+	#[test]
+	fn raw_text_cannot_end_early() {
+		let html = render_to_string(|mut ui| {
+			ui.element("script", "script")
+				.children()
+				.text("if (a < b) { s = '</p></SCRIPT><script>alert(1)</script >'; }");
+			ui.element("style", "style")
+				.children()
+				.text("p::after { content: '</Style>'; }");
+		});
+		assert!(
+			html.contains(">if (a < b) { s = '</p><\\/SCRIPT><script>alert(1)<\\/script >'; }</script>")
+		);
+		assert!(html.contains(">p::after { content: '<\\/Style>'; }</style>"));
+	}
+}
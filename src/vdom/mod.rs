@@ -1,68 +1,432 @@
 //! Provides the virtual DOM implementation for the crate as well as builders.
 
-use std::collections::HashMap;
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Formatter};
+use std::rc::Rc;
 
 use bumpalo::collections::Vec as BVec;
 use bumpalo::Bump;
-use wasm_bindgen::JsCast as _;
-use web_sys::Node;
 
 pub use self::builder::{DomBuilder, ElementBuilder, StaticOrDynamic};
-pub(crate) use self::patch::patch;
-use crate::id::Id;
+pub(crate) use self::builder::{DrawState, Hook, Hooks};
+pub(crate) use self::html::{escape, to_html, RAW_TEXT_ELEMENTS, VOID_ELEMENTS};
+pub(crate) use self::patch::{hydrate, patch, patch_portals, PortalMount};
+use crate::backend::{DomBackend, PropertyValue};
+use crate::event::{EventSet, PREVENT_DEFAULT_DATA_KEY};
+use crate::id::{Id, IdMap, IdSet};
 
 mod builder;
+mod html;
 mod patch;
 
+/// The namespace of HTML elements, which is the default.
+pub const HTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+/// The namespace of SVG elements, which `<svg>` and its descendants are in by default.
+pub const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+/// The namespace of `MathML` elements, which `<math>` and its descendants are in by default.
+pub const MATHML_NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+
 #[derive(Debug)]
 pub(crate) struct VNodeElement<'x> {
 	id: Id,
 	tag: &'x str,
+	/// `None` for HTML elements.
+	namespace: Option<&'static str>,
 	attributes: HashMap<&'x str, &'x str>,
+	/// Properties of the inline style, which are patched individually rather than as the `style` attribute.
+	styles: HashMap<&'x str, &'x str>,
+	/// Classes, which are patched individually rather than as the `class` attribute.
+	classes: HashSet<&'x str>,
+	/// Applied after the children, since some properties depend on them, such as `value` for `<select>`.
+	properties: HashMap<&'x str, PropertyValue<'x>>,
+	/// The events whose default action is always prevented.
+	prevent_default: EventSet,
+	/// The hash of the key passed to [`DomBuilder::memo`], if this element is memoized.
+	memo_key: Option<u64>,
+	/// Set if the children were copied from the last render by [`DomBuilder::memo`], so the DOM within this element is already up to date.
+	memo_reused: bool,
+	/// Set if the element has lifecycle hooks, so [`Notification`]s should be emitted for it.
+	tracked: bool,
 	children: BVec<'x, VNode<'x>>,
 }
 
 impl VNodeElement<'_> {
-	fn to_dom(&self) -> Node {
-		let element = web_sys::window()
-			.unwrap()
-			.document()
-			.unwrap()
-			.create_element(self.tag)
-			.unwrap()
-			.dyn_into::<web_sys::HtmlElement>()
-			.unwrap();
+	/// The value of the dataset entry for [`prevent_default`](Self::prevent_default), if there should be one.
+	fn prevent_default_data(&self) -> Option<String> {
+		(!self.prevent_default.is_empty()).then(|| self.prevent_default.to_string())
+	}
 
-		element
-			.dataset()
-			.set(Id::DATA_KEY, &self.id.to_string())
-			.unwrap();
+	/// Check if the DOM element of `self` can be patched into `other`, rather than replaced.
+	fn is_same_kind(&self, other: &Self) -> bool {
+		self.tag == other.tag && self.namespace == other.namespace
+	}
+
+	fn to_dom<B: DomBackend>(
+		&self,
+		backend: &B,
+		mounted: &mut Vec<Notification<B::Node>>,
+	) -> B::Node {
+		self.to_dom_with(backend, mounted, |element, mounted| {
+			for child in flatten(&self.children) {
+				backend.insert_child(element, &child.to_dom(backend, mounted), None);
+			}
+		})
+	}
+
+	/// Create the DOM for the element, with its content added by `add_content`.
+	fn to_dom_with<B: DomBackend>(
+		&self,
+		backend: &B,
+		mounted: &mut Vec<Notification<B::Node>>,
+		add_content: impl FnOnce(&B::Node, &mut Vec<Notification<B::Node>>),
+	) -> B::Node {
+		let element = match self.namespace {
+			Some(namespace) => backend.create_element_ns(namespace, self.tag),
+			None => backend.create_element(self.tag),
+		};
+
+		backend.set_dataset(&element, Id::DATA_KEY, &self.id.to_string());
+		if let Some(prevent_default) = self.prevent_default_data() {
+			backend.set_dataset(&element, PREVENT_DEFAULT_DATA_KEY, &prevent_default);
+		}
 
 		for (attr, value) in &self.attributes {
-			element.set_attribute(attr, value).unwrap();
+			backend.set_attribute(&element, attr, value);
+		}
+		for (prop, value) in &self.styles {
+			backend.set_style(&element, prop, value);
+		}
+		for class in &self.classes {
+			backend.add_class(&element, class);
+		}
+
+		add_content(&element, mounted);
+
+		for (&name, &value) in &self.properties {
+			backend.set_property(&element, name, value);
 		}
 
-		for child in &self.children {
-			element.append_child(&child.to_dom()).unwrap();
+		if self.tracked {
+			mounted.push(Notification {
+				id: self.id,
+				lifecycle: Lifecycle::Mount,
+				node: element.clone(),
+			});
 		}
 
-		element.into()
+		element
+	}
+
+	fn copy_into<'y>(&self, bump: &'y Bump) -> VNodeElement<'y> {
+		let mut children = BVec::with_capacity_in(self.children.len(), bump);
+		children.extend(self.children.iter().map(|child| child.copy_into(bump)));
+		VNodeElement {
+			id: self.id,
+			tag: bump.alloc_str(self.tag),
+			namespace: self.namespace,
+			attributes: self
+				.attributes
+				.iter()
+				.map(|(&attr, &value)| (&*bump.alloc_str(attr), &*bump.alloc_str(value)))
+				.collect(),
+			styles: self
+				.styles
+				.iter()
+				.map(|(&prop, &value)| (&*bump.alloc_str(prop), &*bump.alloc_str(value)))
+				.collect(),
+			classes: self
+				.classes
+				.iter()
+				.map(|&class| &*bump.alloc_str(class))
+				.collect(),
+			properties: self
+				.properties
+				.iter()
+				.map(|(&name, &value)| (&*bump.alloc_str(name), value.copy_into(bump)))
+				.collect(),
+			prevent_default: self.prevent_default,
+			memo_key: self.memo_key,
+			memo_reused: self.memo_reused,
+			tracked: self.tracked,
+			children,
+		}
+	}
+}
+
+/// Trusted HTML inserted as is into a wrapper element, as added by [`DomBuilder::raw_html`].
+#[derive(Debug)]
+pub(crate) struct VNodeRawHtml<'x> {
+	/// The wrapper element, whose children are ignored in favor of `html`.
+	element: VNodeElement<'x>,
+	html: &'x str,
+}
+
+impl VNodeRawHtml<'_> {
+	fn to_dom<B: DomBackend>(
+		&self,
+		backend: &B,
+		mounted: &mut Vec<Notification<B::Node>>,
+	) -> B::Node {
+		self.element.to_dom_with(backend, mounted, |element, _| {
+			backend.set_inner_html(element, self.html);
+		})
+	}
+}
+
+/// A group of siblings under one ID, as added by [`DomBuilder::fragment`].
+///
+/// Fragments have no DOM of their own; their children are [`flatten`]ed into the children of the parent.
+#[derive(Debug)]
+pub(crate) struct VNodeFragment<'x> {
+	id: Id,
+	children: BVec<'x, VNode<'x>>,
+}
+
+/// Children rendered into another element, as added by [`DomBuilder::portal`].
+///
+/// In the DOM of the parent, a portal is only a placeholder comment.
+/// Its children are patched separately, by [`patch_portals`], into a container added to `target`.
+#[derive(Debug)]
+pub(crate) struct VNodePortal<'x> {
+	id: Id,
+	target: PortalTarget,
+	children: BVec<'x, VNode<'x>>,
+}
+
+impl VNodePortal<'_> {
+	/// The content of the placeholder comment.
+	const PLACEHOLDER: &'static str = "portal";
+}
+
+/// The node that a portal renders into, which is a [`DomBackend::Node`] of whichever backend the virtual DOM is applied to.
+#[derive(Clone)]
+pub(crate) struct PortalTarget(Rc<dyn Any>);
+
+impl PortalTarget {
+	pub(crate) fn new<N: 'static>(node: N) -> Self {
+		Self(Rc::new(node))
+	}
+
+	/// Get the node, which must be a node of `B`.
+	fn node<B: DomBackend>(&self) -> &B::Node {
+		self
+			.0
+			.downcast_ref()
+			.expect("the target of a portal must be a node of the backend that the DOM is built with")
+	}
+}
+
+impl Debug for PortalTarget {
+	fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+		formatter.write_str("PortalTarget")
 	}
 }
 
 #[derive(Debug)]
+// elements are much more common than text, so boxing them would only add indirection
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum VNode<'x> {
 	Text(&'x str),
 	Element(VNodeElement<'x>),
+	RawHtml(VNodeRawHtml<'x>),
+	Fragment(VNodeFragment<'x>),
+	/// Usually a placeholder for content that is absent, which keeps the positions of its siblings stable.
+	Comment(&'x str),
+	Portal(VNodePortal<'x>),
+}
+
+/// Replace the fragments within `nodes` with their children, recursively, which is how the nodes appear in the DOM.
+fn flatten<'v, 'x>(nodes: &'v [VNode<'x>]) -> Vec<&'v VNode<'x>> {
+	fn collect<'v, 'x>(nodes: &'v [VNode<'x>], flattened: &mut Vec<&'v VNode<'x>>) {
+		for node in nodes {
+			match node {
+				VNode::Fragment(fragment) => collect(&fragment.children, flattened),
+				node => flattened.push(node),
+			}
+		}
+	}
+
+	let mut ret = Vec::with_capacity(nodes.len());
+	collect(nodes, &mut ret);
+	ret
 }
 
 impl VNode<'_> {
-	fn to_dom(&self) -> Node {
+	/// Create the DOM for the node, adding a [`Lifecycle::Mount`] notification to `mounted` for each tracked element within it.
+	fn to_dom<B: DomBackend>(
+		&self,
+		backend: &B,
+		mounted: &mut Vec<Notification<B::Node>>,
+	) -> B::Node {
 		match self {
-			Self::Text(text) => web_sys::Text::new_with_data(text).unwrap().into(),
-			Self::Element(element) => element.to_dom(),
+			Self::Text(text) => backend.create_text(text),
+			Self::Element(element) => element.to_dom(backend, mounted),
+			Self::RawHtml(raw) => raw.to_dom(backend, mounted),
+			Self::Comment(text) => backend.create_comment(text),
+			Self::Portal(..) => backend.create_comment(VNodePortal::PLACEHOLDER),
+			Self::Fragment(..) => unreachable!("fragments are flattened into their parent"),
 		}
 	}
+
+	fn contains_tracked(&self) -> bool {
+		match self {
+			// the elements within a portal are not in the DOM of its parent
+			Self::Text(..) | Self::Comment(..) | Self::Portal(..) => false,
+			Self::RawHtml(raw) => raw.element.tracked,
+			Self::Element(element) => {
+				element.tracked || element.children.iter().any(Self::contains_tracked)
+			}
+			Self::Fragment(fragment) => fragment.children.iter().any(Self::contains_tracked),
+		}
+	}
+
+	/// Make a deep copy of the node in `bump`.
+	fn copy_into<'y>(&self, bump: &'y Bump) -> VNode<'y> {
+		match self {
+			Self::Text(text) => VNode::Text(bump.alloc_str(text)),
+			Self::Element(element) => VNode::Element(element.copy_into(bump)),
+			Self::RawHtml(raw) => VNode::RawHtml(VNodeRawHtml {
+				element: raw.element.copy_into(bump),
+				html: bump.alloc_str(raw.html),
+			}),
+			Self::Fragment(fragment) => {
+				let mut children = BVec::with_capacity_in(fragment.children.len(), bump);
+				children.extend(fragment.children.iter().map(|child| child.copy_into(bump)));
+				VNode::Fragment(VNodeFragment {
+					id: fragment.id,
+					children,
+				})
+			}
+			Self::Comment(text) => VNode::Comment(bump.alloc_str(text)),
+			Self::Portal(portal) => {
+				let mut children = BVec::with_capacity_in(portal.children.len(), bump);
+				children.extend(portal.children.iter().map(|child| child.copy_into(bump)));
+				VNode::Portal(VNodePortal {
+					id: portal.id,
+					target: portal.target.clone(),
+					children,
+				})
+			}
+		}
+	}
+}
+
+/// Collect the memoized elements within `nodes`, so their children can be reused by the next render.
+pub(crate) fn memos<'v, 'x>(nodes: &'v [VNode<'x>]) -> IdMap<&'v VNodeElement<'x>> {
+	fn collect<'v, 'x>(nodes: &'v [VNode<'x>], memos: &mut IdMap<&'v VNodeElement<'x>>) {
+		for node in nodes {
+			match node {
+				VNode::Element(element) => {
+					if element.memo_key.is_some() {
+						memos.insert(element.id, element);
+					}
+					collect(&element.children, memos);
+				}
+				VNode::Fragment(fragment) => collect(&fragment.children, memos),
+				VNode::Portal(portal) => collect(&portal.children, memos),
+				_ => {}
+			}
+		}
+	}
+
+	let mut ret = IdMap::default();
+	collect(nodes, &mut ret);
+	ret
+}
+
+/// Collect the IDs of the elements whose children were reused by [`DomBuilder::memo`], along with the elements and fragments within them.
+pub(crate) fn memo_reused_ids(nodes: &[VNode<'_>]) -> IdSet {
+	fn collect(nodes: &[VNode<'_>], within_reused: bool, ids: &mut IdSet) {
+		for node in nodes {
+			match node {
+				VNode::Element(element) => {
+					let within_reused = within_reused || element.memo_reused;
+					if within_reused {
+						ids.insert(element.id);
+					}
+					collect(&element.children, within_reused, ids);
+				}
+				VNode::RawHtml(raw) if within_reused => {
+					ids.insert(raw.element.id);
+				}
+				// fragments and portals can own state, like elements
+				VNode::Fragment(VNodeFragment { id, children })
+				| VNode::Portal(VNodePortal { id, children, .. }) => {
+					if within_reused {
+						ids.insert(*id);
+					}
+					collect(children, within_reused, ids);
+				}
+				_ => {}
+			}
+		}
+	}
+
+	let mut ret = IdSet::default();
+	collect(nodes, false, &mut ret);
+	ret
+}
+
+/// A portal found by [`portals`].
+struct Portal<'v, 'x> {
+	target: &'v PortalTarget,
+	children: &'v [VNode<'x>],
+	/// The IDs of the elements that contain the portal, from its parent up, which events within it propagate to.
+	ancestors: Vec<Id>,
+}
+
+/// Collect the portals within `nodes`, including those nested in other portals, along with their IDs, in tree order.
+fn portals<'v, 'x>(nodes: &'v [VNode<'x>]) -> Vec<(Id, Portal<'v, 'x>)> {
+	fn collect<'v, 'x>(
+		nodes: &'v [VNode<'x>],
+		ancestors: &mut Vec<Id>,
+		portals: &mut Vec<(Id, Portal<'v, 'x>)>,
+	) {
+		for node in nodes {
+			match node {
+				VNode::Element(element) => {
+					ancestors.push(element.id);
+					collect(&element.children, ancestors, portals);
+					ancestors.pop();
+				}
+				VNode::Fragment(fragment) => collect(&fragment.children, ancestors, portals),
+				VNode::Portal(portal) => {
+					let found = Portal {
+						target: &portal.target,
+						children: &portal.children,
+						ancestors: ancestors.iter().rev().copied().collect(),
+					};
+					portals.push((portal.id, found));
+					collect(&portal.children, ancestors, portals);
+				}
+				_ => {}
+			}
+		}
+	}
+
+	let mut ret = Vec::new();
+	collect(nodes, &mut Vec::new(), &mut ret);
+	ret
+}
+
+/// A change in the presence of an element in the DOM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Lifecycle {
+	/// The element was added to the DOM.
+	Mount,
+	/// The element was already in the DOM and was patched.
+	Update,
+	/// The element was removed from the DOM.
+	Unmount,
+}
+
+/// Emitted by [`patch`] and [`hydrate`] for elements with lifecycle hooks.
+#[derive(Debug)]
+pub(crate) struct Notification<N> {
+	pub(crate) id: Id,
+	pub(crate) lifecycle: Lifecycle,
+	pub(crate) node: N,
 }
 
 /*
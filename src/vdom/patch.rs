@@ -1,101 +1,1241 @@
+use std::cell::RefCell;
 use std::cmp::Ordering;
 
-use wasm_bindgen::JsCast as _;
-use web_sys::Node;
-
-use super::VNode;
-use crate::id::Id;
+use super::html::TEXT_SEPARATOR;
+use super::{
+	flatten, portals, Lifecycle, Notification, VNode, VNodeElement, VNodePortal, VNodeRawHtml,
+};
+use crate::backend::{dataset_attr, DomBackend};
+use crate::event::PREVENT_DEFAULT_DATA_KEY;
+use crate::id::{Id, IdMap, IdSet};
 
 /// Something is wrong in the DOM, probably due to tampering. It must be rebuilt entirely.
 struct MustRegenerate;
 
-fn patch_fallible(dom: &Node, old: &[VNode<'_>], new: &[VNode<'_>]) -> Result<(), MustRegenerate> {
-	patch_fallible_unkeyed(dom, old, new)
+/// If every node is an element and their IDs are unique, get the elements so they can be matched up by ID.
+fn keyed<'v, 'x>(nodes: &[&'v VNode<'x>]) -> Option<Vec<&'v VNodeElement<'x>>> {
+	let mut seen = IdSet::default();
+	nodes
+		.iter()
+		.map(|&node| match node {
+			VNode::Element(element) if seen.insert(element.id) => Some(element),
+			_ => None,
+		})
+		.collect()
 }
 
-fn patch_fallible_unkeyed(
-	dom: &Node,
-	old: &[VNode<'_>],
-	new: &[VNode<'_>],
-) -> Result<(), MustRegenerate> {
-	let dom_children = dom.child_nodes();
-
-	for (i, (old, new)) in old.iter().zip(new.iter()).enumerate() {
-		let i = u32::try_from(i).unwrap();
-		match (old, new) {
-			(VNode::Text(old), VNode::Text(new)) => {
-				if old != new {
-					dom_children
-						.item(i)
-						.ok_or(MustRegenerate)?
-						.set_node_value(Some(new));
+/// Mark the positions of the `Some` values in `sequence` that form its longest strictly increasing subsequence.
+fn longest_increasing_subsequence(sequence: &[Option<usize>]) -> Vec<bool> {
+	// `tails[k]` is the position of the smallest value that ends an increasing subsequence of length `k + 1`.
+	let mut tails: Vec<usize> = Vec::new();
+	let mut predecessors = vec![None; sequence.len()];
+
+	for (position, value) in sequence.iter().enumerate() {
+		let Some(value) = *value else { continue };
+		let length = tails.partition_point(|&tail| sequence[tail].unwrap() < value);
+		if length > 0 {
+			predecessors[position] = Some(tails[length - 1]);
+		}
+		if length == tails.len() {
+			tails.push(position);
+		} else {
+			tails[length] = position;
+		}
+	}
+
+	let mut ret = vec![false; sequence.len()];
+	let mut current = tails.last().copied();
+	while let Some(position) = current {
+		ret[position] = true;
+		current = predecessors[position];
+	}
+	ret
+}
+
+fn set_or_remove_dataset<B: DomBackend>(
+	backend: &B,
+	element: &B::Node,
+	key: &str,
+	value: Option<String>,
+) {
+	match value {
+		Some(value) => backend.set_dataset(element, key, &value),
+		None => backend.remove_dataset(element, key),
+	}
+}
+
+struct Patcher<'b, B: DomBackend> {
+	backend: &'b B,
+	notifications: RefCell<Vec<Notification<B::Node>>>,
+}
+
+impl<'b, B: DomBackend> Patcher<'b, B> {
+	fn new(backend: &'b B) -> Self {
+		Self {
+			backend,
+			notifications: RefCell::default(),
+		}
+	}
+
+	fn notify(&self, id: Id, lifecycle: Lifecycle, node: &B::Node) {
+		self.notifications.borrow_mut().push(Notification {
+			id,
+			lifecycle,
+			node: node.clone(),
+		});
+	}
+
+	/// Create the DOM for `new`, notifying of the tracked elements within it.
+	fn create(&self, new: &VNode<'_>) -> B::Node {
+		new.to_dom(self.backend, &mut self.notifications.borrow_mut())
+	}
+
+	/// Notify of the tracked elements within `old`, whose DOM is `dom`, as it is being removed.
+	fn unmount(&self, dom: &B::Node, old: &VNode<'_>) {
+		match old {
+			VNode::Element(old) => self.unmount_element(dom, old),
+			VNode::RawHtml(old) if old.element.tracked => {
+				self.notify(old.element.id, Lifecycle::Unmount, dom);
+			}
+			_ => {}
+		}
+	}
+
+	fn unmount_element(&self, dom: &B::Node, old: &VNodeElement<'_>) {
+		if old.tracked {
+			self.notify(old.id, Lifecycle::Unmount, dom);
+		}
+		// avoid querying the DOM if there is nothing to notify of
+		if !old.children.iter().any(VNode::contains_tracked) {
+			return;
+		}
+		for (dom_child, old_child) in self
+			.backend
+			.children(dom)
+			.iter()
+			.zip(flatten(&old.children))
+		{
+			self.unmount(dom_child, old_child);
+		}
+	}
+
+	fn patch_fallible(
+		&self,
+		dom: &B::Node,
+		old: &[VNode<'_>],
+		new: &[VNode<'_>],
+	) -> Result<(), MustRegenerate> {
+		let old = flatten(old);
+		let new = flatten(new);
+		match (keyed(&old), keyed(&new)) {
+			(Some(old), Some(new)) => self.patch_fallible_keyed(dom, &old, &new),
+			_ => self.patch_fallible_unkeyed(dom, &old, &new),
+		}
+	}
+
+	fn patch_element(
+		&self,
+		dom: &B::Node,
+		old: &VNodeElement<'_>,
+		new: &VNodeElement<'_>,
+	) -> Result<(), MustRegenerate> {
+		self.patch_element_with(dom, old, new, || {
+			// the children of a memoized element that were copied from the last render are already in the DOM
+			if new.memo_reused && old.id == new.id {
+				return Ok(());
+			}
+			self.patch_fallible(dom, &old.children, &new.children)
+		})
+	}
+
+	fn patch_raw_html(
+		&self,
+		dom: &B::Node,
+		old: &VNodeRawHtml<'_>,
+		new: &VNodeRawHtml<'_>,
+	) -> Result<(), MustRegenerate> {
+		self.patch_element_with(dom, &old.element, &new.element, || {
+			if old.html != new.html {
+				self.backend.set_inner_html(dom, new.html);
+			}
+			Ok(())
+		})
+	}
+
+	/// Patch the element `dom` from `old` to `new`, with its content patched by `patch_content`.
+	fn patch_element_with(
+		&self,
+		dom: &B::Node,
+		old: &VNodeElement<'_>,
+		new: &VNodeElement<'_>,
+		patch_content: impl FnOnce() -> Result<(), MustRegenerate>,
+	) -> Result<(), MustRegenerate> {
+		let backend = self.backend;
+
+		if old.id != new.id {
+			backend.set_dataset(dom, Id::DATA_KEY, &new.id.to_string());
+			if old.tracked {
+				self.notify(old.id, Lifecycle::Unmount, dom);
+			}
+		}
+		if old.prevent_default != new.prevent_default {
+			set_or_remove_dataset(
+				backend,
+				dom,
+				PREVENT_DEFAULT_DATA_KEY,
+				new.prevent_default_data(),
+			);
+		}
+
+		for removed in old
+			.attributes
+			.keys()
+			.filter(|&attr| !new.attributes.contains_key(attr))
+		{
+			backend.remove_attribute(dom, removed);
+		}
+		for (added_or_modified, value) in new
+			.attributes
+			.iter()
+			.filter(|&(attr, value)| old.attributes.get(attr) != Some(value))
+		{
+			backend.set_attribute(dom, added_or_modified, value);
+		}
+
+		for removed in old
+			.styles
+			.keys()
+			.filter(|&prop| !new.styles.contains_key(prop))
+		{
+			backend.remove_style(dom, removed);
+		}
+		for (added_or_modified, value) in new
+			.styles
+			.iter()
+			.filter(|&(prop, value)| old.styles.get(prop) != Some(value))
+		{
+			backend.set_style(dom, added_or_modified, value);
+		}
+
+		for removed in old.classes.difference(&new.classes) {
+			backend.remove_class(dom, removed);
+		}
+		for added in new.classes.difference(&old.classes) {
+			backend.add_class(dom, added);
+		}
+
+		patch_content()?;
+
+		self.patch_properties(dom, old, new);
+
+		if new.tracked {
+			let lifecycle = if old.id == new.id {
+				Lifecycle::Update
+			} else {
+				Lifecycle::Mount
+			};
+			self.notify(new.id, lifecycle, dom);
+		}
+
+		Ok(())
+	}
+
+	fn patch_properties(&self, dom: &B::Node, old: &VNodeElement<'_>, new: &VNodeElement<'_>) {
+		let backend = self.backend;
+
+		for (&removed, &value) in old
+			.properties
+			.iter()
+			.filter(|&(name, _)| !new.properties.contains_key(name))
+		{
+			backend.set_property(dom, removed, value.reset());
+		}
+		// unchanged properties are left alone, as the user may have changed them since, such as by typing into an `<input>`
+		for (&name, &value) in new
+			.properties
+			.iter()
+			.filter(|&(name, value)| old.properties.get(name) != Some(value))
+		{
+			backend.set_property(dom, name, value);
+		}
+	}
+
+	fn patch_fallible_unkeyed(
+		&self,
+		dom: &B::Node,
+		old: &[&VNode<'_>],
+		new: &[&VNode<'_>],
+	) -> Result<(), MustRegenerate> {
+		let backend = self.backend;
+		let dom_children = backend.children(dom);
+
+		for (i, (&old, &new)) in old.iter().zip(new.iter()).enumerate() {
+			let dom_child = dom_children.get(i).ok_or(MustRegenerate)?;
+			match (old, new) {
+				(VNode::Text(old), VNode::Text(new)) | (VNode::Comment(old), VNode::Comment(new)) => {
+					if old != new {
+						backend.set_text(dom_child, new);
+					}
+				}
+				// only the placeholders are in this DOM
+				(VNode::Portal(..), VNode::Portal(..)) => {}
+				(VNode::Element(old), VNode::Element(new)) if old.is_same_kind(new) => {
+					self.patch_element(dom_child, old, new)?;
+				}
+				(VNode::RawHtml(old), VNode::RawHtml(new)) if old.element.is_same_kind(&new.element) => {
+					self.patch_raw_html(dom_child, old, new)?;
+				}
+				(old, new) => {
+					self.unmount(dom_child, old);
+					backend.replace_child(dom, &self.create(new), dom_child);
 				}
 			}
-			(VNode::Element(old), VNode::Element(new)) if old.tag == new.tag => {
-				let dom_child = dom_children.item(i).ok_or(MustRegenerate)?;
-				let dom_child = dom_child.dyn_into::<web_sys::HtmlElement>().unwrap();
+		}
 
-				if old.id != new.id {
-					dom_child
-						.dataset()
-						.set(Id::DATA_KEY, &new.id.to_string())
-						.unwrap();
+		match new.len().cmp(&old.len()) {
+			Ordering::Greater => {
+				for new in new.iter().skip(old.len()) {
+					backend.insert_child(dom, &self.create(new), None);
+				}
+			}
+			Ordering::Less => {
+				let removed = dom_children
+					.get(new.len()..old.len())
+					.ok_or(MustRegenerate)?;
+				for (dom_child, old) in removed.iter().zip(&old[new.len()..]) {
+					self.unmount(dom_child, old);
+					backend.remove_child(dom, dom_child);
 				}
+			}
+			Ordering::Equal => {}
+		}
+
+		Ok(())
+	}
+
+	/// Match children up by ID so that insertions, removals, and reorderings only touch the DOM nodes that actually changed.
+	///
+	/// Children that are kept and whose relative order is unchanged (the longest increasing subsequence of their old indices) are left in place; all others are moved or created.
+	fn patch_fallible_keyed(
+		&self,
+		dom: &B::Node,
+		old: &[&VNodeElement<'_>],
+		new: &[&VNodeElement<'_>],
+	) -> Result<(), MustRegenerate> {
+		let backend = self.backend;
+
+		let old_dom = backend.children(dom);
+		if old_dom.len() != old.len() {
+			return Err(MustRegenerate);
+		}
+
+		let old_indices: IdMap<usize> = old.iter().enumerate().map(|(i, old)| (old.id, i)).collect();
+
+		// For each new child, the index of the old child that it reuses, if any.
+		let sources: Vec<Option<usize>> = new
+			.iter()
+			.map(|new| {
+				old_indices
+					.get(&new.id)
+					.copied()
+					.filter(|&i| old[i].is_same_kind(new))
+			})
+			.collect();
+
+		let mut reused = vec![false; old.len()];
+		for &source in sources.iter().flatten() {
+			reused[source] = true;
+		}
+		for ((old_dom, old), _) in old_dom
+			.iter()
+			.zip(old)
+			.zip(&reused)
+			.filter(|(_, &reused)| !reused)
+		{
+			self.unmount_element(old_dom, old);
+			backend.remove_child(dom, old_dom);
+		}
+
+		for (new, source) in new.iter().zip(&sources) {
+			if let Some(source) = *source {
+				self.patch_element(&old_dom[source], old[source], new)?;
+			}
+		}
+
+		let stable = longest_increasing_subsequence(&sources);
+		let mut next: Option<B::Node> = None;
+		for ((new, source), stable) in new.iter().zip(&sources).zip(stable).rev() {
+			let node = match *source {
+				Some(source) => old_dom[source].clone(),
+				None => new.to_dom(backend, &mut self.notifications.borrow_mut()),
+			};
+			if !stable {
+				backend.insert_child(dom, &node, next.as_ref());
+			}
+			next = Some(node);
+		}
+
+		Ok(())
+	}
 
-				for removed in old
-					.attributes
-					.keys()
-					.filter(|&attr| !new.attributes.contains_key(attr))
-				{
-					dom_child.remove_attribute(removed).unwrap();
+	/// Adopt the existing children of `dom` as the rendering of `new`, rebuilding only the subtrees that don't match.
+	fn hydrate(&self, dom: &B::Node, new: &[VNode<'_>]) {
+		let backend = self.backend;
+		let mut dom_children = backend.children(dom).into_iter().peekable();
+		let mut after_text = false;
+
+		for new in flatten(new) {
+			let is_text = matches!(new, VNode::Text(..));
+			// the separator that `to_html` writes between adjacent text nodes isn't part of the virtual DOM
+			if after_text && is_text {
+				let separator =
+					dom_children.next_if(|child| backend.comment(child).as_deref() == Some(TEXT_SEPARATOR));
+				if let Some(separator) = separator {
+					backend.remove_child(dom, &separator);
 				}
-				for (added_or_modified, value) in new
-					.attributes
-					.iter()
-					.filter(|&(attr, value)| old.attributes.get(attr) != Some(value))
-				{
-					dom_child.set_attribute(added_or_modified, value).unwrap();
+			}
+			after_text = is_text;
+
+			match dom_children.next() {
+				Some(dom_child) => {
+					if !self.hydrate_node(&dom_child, new) {
+						backend.replace_child(dom, &self.create(new), &dom_child);
+					}
 				}
+				None => backend.insert_child(dom, &self.create(new), None),
+			}
+		}
+
+		for extra in dom_children {
+			backend.remove_child(dom, &extra);
+		}
+	}
 
-				patch_fallible(&dom_child, &old.children, &new.children)?;
+	/// Returns `false` if `dom` is not the same kind of node as `new` and must be replaced.
+	fn hydrate_node(&self, dom: &B::Node, new: &VNode<'_>) -> bool {
+		let backend = self.backend;
+		match new {
+			VNode::Text(new) => {
+				let Some(old) = backend.text(dom) else { return false; };
+				if old != *new {
+					backend.set_text(dom, new);
+				}
+				true
+			}
+			VNode::Comment(new) => {
+				let Some(old) = backend.comment(dom) else { return false; };
+				if old != *new {
+					backend.set_text(dom, new);
+				}
+				true
 			}
-			(_, new) => {
-				let dom_child = dom_children.item(i).ok_or(MustRegenerate)?;
-				// new comes before old here
-				dom.replace_child(&new.to_dom(), &dom_child).unwrap();
+			VNode::Portal(..) => {
+				let Some(old) = backend.comment(dom) else { return false; };
+				if old != VNodePortal::PLACEHOLDER {
+					backend.set_text(dom, VNodePortal::PLACEHOLDER);
+				}
+				true
 			}
+			VNode::Fragment(..) => unreachable!("fragments are flattened into their parent"),
+			VNode::Element(new) => self.hydrate_element(dom, new, || self.hydrate(dom, &new.children)),
+			VNode::RawHtml(new) => self.hydrate_element(dom, &new.element, || {
+				// the browser normalizes the HTML, so this may set it again even if it didn't change, which is harmless
+				if backend.inner_html(dom).as_deref() != Some(new.html) {
+					backend.set_inner_html(dom, new.html);
+				}
+			}),
 		}
 	}
 
-	match new.len().cmp(&old.len()) {
-		Ordering::Greater => {
-			for new in new.iter().skip(old.len()) {
-				dom.append_child(&new.to_dom()).unwrap();
+	/// Like [`hydrate_node`](Self::hydrate_node) for an element, with its content hydrated by `hydrate_content`.
+	fn hydrate_element(
+		&self,
+		dom: &B::Node,
+		new: &VNodeElement<'_>,
+		hydrate_content: impl FnOnce(),
+	) -> bool {
+		let backend = self.backend;
+		let matches = matches!(backend.tag(dom), Some(tag) if tag.eq_ignore_ascii_case(new.tag))
+			&& backend.namespace(dom).as_deref() == new.namespace
+			&& backend.dataset(dom, Id::DATA_KEY) == Some(new.id.to_string());
+		if !matches {
+			return false;
+		}
+
+		let prevent_default = new.prevent_default_data();
+		if backend.dataset(dom, PREVENT_DEFAULT_DATA_KEY) != prevent_default {
+			set_or_remove_dataset(backend, dom, PREVENT_DEFAULT_DATA_KEY, prevent_default);
+		}
+
+		// attributes only rendered by the server are removed, keeping the bookkeeping in the dataset
+		// `class` and `style` are also removed unless set as attributes, so only the classes and style properties set below remain
+		let bookkeeping = dataset_attr("__domi");
+		for attr in backend.attribute_names(dom) {
+			if !new.attributes.contains_key(attr.as_str()) && !attr.starts_with(&bookkeeping) {
+				backend.remove_attribute(dom, &attr);
 			}
 		}
-		Ordering::Less => {
-			for _ in new.len()..old.len() {
-				dom
-					.remove_child(&dom.last_child().ok_or(MustRegenerate)?)
-					.unwrap();
+		for (attr, value) in &new.attributes {
+			if backend.attribute(dom, attr).as_deref() != Some(*value) {
+				backend.set_attribute(dom, attr, value);
 			}
 		}
-		Ordering::Equal => {}
+		for (prop, value) in &new.styles {
+			backend.set_style(dom, prop, value);
+		}
+		for class in &new.classes {
+			backend.add_class(dom, class);
+		}
+
+		hydrate_content();
+
+		for (&name, &value) in &new.properties {
+			backend.set_property(dom, name, value);
+		}
+
+		if new.tracked {
+			self.notify(new.id, Lifecycle::Mount, dom);
+		}
+
+		true
 	}
 
-	Ok(())
+	fn regenerate(&self, dom: &B::Node, old: &[VNode<'_>], new: &[VNode<'_>]) {
+		let backend = self.backend;
+
+		// the nodes mounted by the failed patch are about to be removed, but those already unmounted are gone for good
+		self
+			.notifications
+			.borrow_mut()
+			.retain(|notification| notification.lifecycle == Lifecycle::Unmount);
+		// the DOM doesn't match `old`, so this is a best effort; unmounting an element twice is harmless
+		for (dom_child, old) in backend.children(dom).iter().zip(flatten(old)) {
+			self.unmount(dom_child, old);
+		}
+
+		for dom_child in backend.children(dom) {
+			backend.remove_child(dom, &dom_child);
+		}
+		for node in flatten(new) {
+			backend.insert_child(dom, &self.create(node), None);
+		}
+	}
 }
 
-pub(crate) fn patch(dom: &Node, old: &[VNode<'_>], new: &[VNode<'_>]) {
-	match patch_fallible(dom, old, new) {
+/// Patch the children of `dom` from `old` to `new`, returning [`Notification`]s for the tracked elements that were affected.
+pub(crate) fn patch<B: DomBackend>(
+	backend: &B,
+	dom: &B::Node,
+	old: &[VNode<'_>],
+	new: &[VNode<'_>],
+) -> Vec<Notification<B::Node>> {
+	let patcher = Patcher::new(backend);
+	match patcher.patch_fallible(dom, old, new) {
 		Ok(()) => (),
-		Err(MustRegenerate) => {
-			while let Some(dom_child) = dom.first_child() {
-				dom.remove_child(&dom_child).unwrap();
+		Err(MustRegenerate) => patcher.regenerate(dom, old, new),
+	}
+	patcher.notifications.into_inner()
+}
+
+/// The DOM of a portal, which is in a container added to its target rather than in the DOM of its parent.
+pub(crate) struct PortalMount<N> {
+	target: N,
+	pub(crate) container: N,
+	/// The IDs of the elements that contain the portal in the virtual DOM, which events within it propagate to.
+	pub(crate) ancestors: Vec<Id>,
+}
+
+/// Patch the children of the portals within `old` and `new` into the containers in `mounts`, after the DOM of their parents has been patched.
+///
+/// New portals, and those whose target changed, get a new container, which `on_create` is called with.
+pub(crate) fn patch_portals<B: DomBackend>(
+	backend: &B,
+	mounts: &mut IdMap<PortalMount<B::Node>>,
+	old: &[VNode<'_>],
+	new: &[VNode<'_>],
+	mut on_create: impl FnMut(&B::Node),
+) -> Vec<Notification<B::Node>> {
+	let old: IdMap<_> = portals(old).into_iter().collect();
+	let new = portals(new);
+	let mut notifications = Vec::new();
+
+	// portals that were removed or moved to another target are emptied first, so their elements are unmounted
+	let mut kept = IdMap::default();
+	for (id, mount) in mounts.drain() {
+		let target = new
+			.iter()
+			.find_map(|(new_id, portal)| (*new_id == id).then(|| portal.target.node::<B>()));
+		if target == Some(&mount.target) {
+			kept.insert(id, mount);
+			continue;
+		}
+		let old_children = old.get(&id).map_or(&[][..], |portal| portal.children);
+		notifications.extend(patch(backend, &mount.container, old_children, &[]));
+		backend.remove_child(&mount.target, &mount.container);
+	}
+
+	// in tree order, so that portals with the same target are in the same order in it
+	for (index, (id, portal)) in new.iter().enumerate() {
+		let target = portal.target.node::<B>();
+		let (container, old_children) = if let Some(mount) = kept.remove(id) {
+			let old_children = old.get(id).map_or(&[][..], |portal| portal.children);
+			(mount.container, old_children)
+		} else {
+			// before the container of the next portal with the same target that is already in it, if any
+			let before = new[index + 1..].iter().find_map(|(next_id, next)| {
+				let mount = kept.get(next_id)?;
+				(next.target.node::<B>() == target).then_some(&mount.container)
+			});
+			let container = backend.create_element("div");
+			backend.insert_child(target, &container, before);
+			on_create(&container);
+			(container, &[][..])
+		};
+		notifications.extend(patch(backend, &container, old_children, portal.children));
+		let mount = PortalMount {
+			target: target.clone(),
+			container,
+			ancestors: portal.ancestors.clone(),
+		};
+		mounts.insert(*id, mount);
+	}
+
+	notifications
+}
+
+/// Like [`patch`], but adopt the nodes already in `dom` instead of assuming it is empty, such as when it contains HTML rendered on the server.
+pub(crate) fn hydrate<B: DomBackend>(
+	backend: &B,
+	dom: &B::Node,
+	new: &[VNode<'_>],
+) -> Vec<Notification<B::Node>> {
+	let patcher = Patcher::new(backend);
+	patcher.hydrate(dom, new);
+	patcher.notifications.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fmt::Write as _;
+
+	use super::{hydrate, patch, patch_portals, PortalMount};
+	use crate::backend::{DomBackend, MemoryDom, MemoryNode, PropertyValue};
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	use crate::id::{Id, IdMap, IdSet};
+	use crate::state::States;
+	use crate::vdom::{
+		memo_reused_ids, memos, DrawState, Lifecycle, PortalTarget, VNode, VNodes, SVG_NAMESPACE,
+	};
+	use crate::DomBuilder;
+
+	fn build(render: impl FnOnce(DomBuilder<'_, '_>)) -> VNodes {
+		rebuild(&VNodes::default(), render)
+	}
+
+	/// Build the virtual DOM with `render`, reusing the memoized elements of `last`.
+	fn rebuild(last: &VNodes, render: impl FnOnce(DomBuilder<'_, '_>)) -> VNodes {
+		let mut vdom = VNodes::default();
+		let state = DrawState {
+			memos: memos(last.children()),
+			..DrawState::default()
+		};
+		vdom.with_children_mut(|children| render(DomBuilder::new(Some(children), None, None, &state)));
+		vdom
+	}
+
+	/// Patch `root` from `last` to the result of `render`, returning the new virtual DOM.
+	fn patch_with(
+		root: &MemoryNode,
+		last: &VNodes,
+		render: impl FnOnce(DomBuilder<'_, '_>),
+	) -> VNodes {
+		let current = rebuild(last, render);
+		patch(&MemoryDom, root, last.children(), current.children());
+		current
+	}
+
+	/// Describe the children of `node` as HTML, leaving out the `data-` attributes used for bookkeeping.
+	fn outline(node: &MemoryNode) -> String {
+		let mut out = String::new();
+		for child in node.children() {
+			if let Some(text) = child.text() {
+				out.push_str(&text);
+			} else if let Some(comment) = child.comment() {
+				write!(out, "<!--{comment}-->").unwrap();
+			} else {
+				let tag = child.tag().unwrap();
+				write!(out, "<{tag}").unwrap();
+				for (attr, value) in child.attributes() {
+					if !attr.starts_with("data-") {
+						write!(out, " {attr}={value:?}").unwrap();
+					}
+				}
+				write!(out, ">{}</{tag}>", outline(&child)).unwrap();
+			}
+		}
+		out
+	}
+
+	#[test]
+	fn create() {
+		let root = MemoryDom.create_element("div");
+		patch_with(&root, &VNodes::default(), |mut ui| {
+			ui.element("p", "p")
+				.attr("title", "greeting")
+				.children()
+				.text("hello");
+			ui.text("world");
+		});
+		assert_eq!(outline(&root), r#"<p title="greeting">hello</p>world"#);
+	}
+
+	#[test]
+	fn update_in_place() {
+		let root = MemoryDom.create_element("div");
+		let last = patch_with(&root, &VNodes::default(), |mut ui| {
+			let mut p = ui.element("p", "p");
+			p.attr("title", "a")
+				.attr("lang", "en")
+				.style("color", "red")
+				.prop_value("one");
+			p.children().text("before");
+		});
+		let p = root.children()[0].clone();
+		let text = p.children()[0].clone();
+
+		patch_with(&root, &last, |mut ui| {
+			let mut p = ui.element("p", "p");
+			p.attr("title", "b").prop_value("two");
+			p.children().text("after");
+		});
+		assert_eq!(outline(&root), r#"<p title="b">after</p>"#);
+		assert!(root.children()[0] == p);
+		assert!(p.children()[0] == text);
+		assert_eq!(p.style("color"), None);
+		assert_eq!(p.string_property("value").as_deref(), Some("two"));
+	}
+
+	#[test]
+	fn user_edits_survive_patch() {
+		let render = |value: &'static str| {
+			move |mut ui: DomBuilder<'_, '_>| {
+				ui.element("input", "input").prop_value(value);
+			}
+		};
+		let root = MemoryDom.create_element("div");
+		let last = patch_with(&root, &VNodes::default(), render("a"));
+		let input = root.children()[0].clone();
+
+		// typed by the user
+		MemoryDom.set_property(&input, "value", PropertyValue::String("ab"));
+		let last = patch_with(&root, &last, render("a"));
+		assert_eq!(input.string_property("value").as_deref(), Some("ab"));
+
+		// but a new value from the app replaces it
+		patch_with(&root, &last, render("c"));
+		assert_eq!(input.string_property("value").as_deref(), Some("c"));
+	}
+
+	#[test]
+	fn class_names_are_split() {
+		let root = MemoryDom.create_element("div");
+		let last = patch_with(&root, &VNodes::default(), |mut ui| {
+			ui.element("p", "p").class(" a\tb  ").class("");
+		});
+		let class = root.children()[0].attribute("class").unwrap();
+		let mut classes: Vec<_> = class.split(' ').collect();
+		classes.sort_unstable();
+		assert_eq!(classes, ["a", "b"]);
+
+		patch_with(&root, &last, |mut ui| {
+			ui.element("p", "p").class("b").class("c");
+		});
+		assert_eq!(outline(&root), r#"<p class="b c"></p>"#);
+	}
+
+	#[test]
+	fn memo() {
+		let render = |key: u32, text: &'static str| {
+			move |mut ui: DomBuilder<'_, '_>| {
+				ui.memo("memo", "div", key, |mut ui| {
+					ui.element("p", "p").children().text(text);
+				});
+			}
+		};
+		let root = MemoryDom.create_element("div");
+		let last = patch_with(&root, &VNodes::default(), render(1, "a"));
+		let p = root.children()[0].children()[0].clone();
+		let text = p.children()[0].clone();
+
+		// the children are neither rebuilt nor patched, so even changes made by other code are kept
+		MemoryDom.set_text(&text, "edited");
+		let last = patch_with(&root, &last, render(1, "b"));
+		assert_eq!(outline(&root), "<div><p>edited</p></div>");
+
+		// a new key patches the children from those of the last render
+		patch_with(&root, &last, render(2, "b"));
+		assert_eq!(outline(&root), "<div><p>b</p></div>");
+		assert!(root.children()[0].children()[0] == p);
+		assert!(p.children()[0] == text);
+	}
+
+	#[test]
+	fn memo_keeps_states_and_hooks() {
+		let render = |key: u32| {
+			move |mut ui: DomBuilder<'_, '_>| {
+				ui.memo("memo", "div", key, |mut ui| {
+					ui.element("p", "p").on_unmount(|_| {});
+				});
+			}
+		};
+		let p = Id::new("memo").with("p");
+		let state = Id::new("state");
+		let mut states = States::default();
+		states.insert(state, Some(p), Rc::new(RefCell::new(0)));
+		states.collect_garbage(&IdSet::default());
+
+		let root = MemoryDom.create_element("div");
+		let last = patch_with(&root, &VNodes::default(), render(1));
+
+		// the state isn't requested and the hook isn't registered again, as the children aren't rebuilt
+		let current = rebuild(&last, render(1));
+		let notifications = patch(&MemoryDom, &root, last.children(), current.children());
+		assert!(notifications
+			.iter()
+			.all(|notification| notification.lifecycle != Lifecycle::Unmount));
+		let kept = memo_reused_ids(current.children());
+		assert!(kept.contains(&p));
+		states.collect_garbage(&kept);
+		assert!(states.get::<i32>(state).is_some());
+
+		let rebuilt = rebuild(&current, render(2));
+		assert!(!memo_reused_ids(rebuilt.children()).contains(&p));
+	}
+
+	#[test]
+	fn add_and_remove_children() {
+		let root = MemoryDom.create_element("div");
+		let last = patch_with(&root, &VNodes::default(), |mut ui| {
+			ui.text("a");
+			ui.text("b");
+		});
+		let first = root.children()[0].clone();
+
+		let last = patch_with(&root, &last, |mut ui| {
+			ui.text("a");
+			ui.text("b");
+			ui.element("c", "span");
+		});
+		assert_eq!(outline(&root), "ab<span></span>");
+		assert!(root.children()[0] == first);
+
+		patch_with(&root, &last, |mut ui| ui.text("z"));
+		assert_eq!(outline(&root), "z");
+		assert!(root.children()[0] == first);
+	}
+
+	#[test]
+	fn replace_different_kind() {
+		let root = MemoryDom.create_element("div");
+		let last = patch_with(&root, &VNodes::default(), |mut ui| {
+			ui.text("text");
+			ui.element("x", "span").children().text("kept");
+		});
+		let span = root.children()[1].clone();
+
+		patch_with(&root, &last, |mut ui| {
+			ui.element("y", "b");
+			ui.element("x", "span").children().text("kept");
+		});
+		assert_eq!(outline(&root), "<b></b><span>kept</span>");
+		assert!(root.children()[1] == span);
+	}
+
+	#[test]
+	fn hydrate_adopts_existing_nodes() {
+		let root = MemoryDom.create_element("div");
+		let render = |mut ui: DomBuilder<'_, '_>| {
+			ui.element("p", "p")
+				.attr("title", "x")
+				.children()
+				.text("same");
+		};
+		patch_with(&root, &VNodes::default(), render);
+		let p = root.children()[0].clone();
+		let text = p.children()[0].clone();
+		let stray = MemoryDom.create_text("stray");
+		MemoryDom.insert_child(&root, &stray, None);
+
+		let current = build(|mut ui| {
+			ui.element("p", "p")
+				.attr("title", "y")
+				.children()
+				.text("changed");
+		});
+		hydrate(&MemoryDom, &root, current.children());
+		assert_eq!(outline(&root), r#"<p title="y">changed</p>"#);
+		assert!(root.children()[0] == p);
+		assert!(p.children()[0] == text);
+	}
+
+	#[test]
+	fn hydrate_removes_server_only_attributes() {
+		let root = MemoryDom.create_element("div");
+		patch_with(&root, &VNodes::default(), |mut ui| {
+			ui.element("p", "p")
+				.attr("title", "x")
+				.attr("hidden", "")
+				.class("server")
+				.class("both")
+				.style("color", "red")
+				.style("margin", "0");
+		});
+		let p = root.children()[0].clone();
+
+		let current = build(|mut ui| {
+			ui.element("p", "p")
+				.attr("title", "y")
+				.class("both")
+				.style("margin", "1px");
+		});
+		hydrate(&MemoryDom, &root, current.children());
+		assert!(root.children()[0] == p);
+		assert_eq!(outline(&root), r#"<p class="both" title="y"></p>"#);
+		assert_eq!(p.style("color"), None);
+		assert_eq!(p.style("margin").as_deref(), Some("1px"));
+		// the ID is kept
+		assert!(p
+			.attributes()
+			.iter()
+			.any(|(attr, _)| attr.starts_with("data-")));
+	}
+
+	#[test]
+	fn hydrate_replaces_mismatched_nodes() {
+		let root = MemoryDom.create_element("div");
+		let span = MemoryDom.create_element("span");
+		MemoryDom.insert_child(&root, &span, None);
+
+		let current = build(|mut ui| {
+			ui.element("p", "p").children().text("new");
+		});
+		hydrate(&MemoryDom, &root, current.children());
+		assert_eq!(outline(&root), "<p>new</p>");
+		assert!(root.children()[0] != span);
+	}
+
+	#[test]
+	fn raw_html_builds_no_children() {
+		let vdom = build(|mut ui| {
+			ui.raw_html("help", "section", "<b>trusted</b>")
+				.children()
+				.element("p", "p")
+				.children()
+				.text("ignored");
+		});
+		let [VNode::RawHtml(raw)] = vdom.children() else {
+			panic!("expected raw HTML")
+		};
+		assert!(raw.element.children.is_empty());
+	}
+
+	#[test]
+	fn raw_html() {
+		let root = MemoryDom.create_element("div");
+		let last = patch_with(&root, &VNodes::default(), |mut ui| {
+			ui.raw_html("help", "section", "<b>old</b>")
+				.attr("title", "a");
+			ui.element("svg", "svg")
+				.children()
+				.raw_html("icon", "g", "<path/>");
+		});
+		let section = root.children()[0].clone();
+		let g = root.children()[1].children()[0].clone();
+		assert_eq!(section.inner_html().as_deref(), Some("<b>old</b>"));
+		assert_eq!(g.namespace().as_deref(), Some(SVG_NAMESPACE));
+
+		patch_with(&root, &last, |mut ui| {
+			ui.raw_html("help", "section", "<i>new</i>")
+				.attr("title", "b");
+			ui.element("svg", "svg")
+				.children()
+				.raw_html("icon", "g", "<path/>");
+		});
+		assert!(root.children()[0] == section);
+		assert_eq!(section.attribute("title").as_deref(), Some("b"));
+		assert_eq!(section.inner_html().as_deref(), Some("<i>new</i>"));
+		assert!(root.children()[1].children()[0] == g);
+	}
+
+	#[test]
+	fn hydrate_adjacent_text() {
+		// as the browser parses the output of `to_html` for two adjacent text nodes
+		let root = MemoryDom.create_element("div");
+		let first = MemoryDom.create_text("a");
+		let second = MemoryDom.create_text("b");
+		MemoryDom.insert_child(&root, &first, None);
+		MemoryDom.insert_child(&root, &MemoryDom.create_comment(""), None);
+		MemoryDom.insert_child(&root, &second, None);
+
+		let current = build(|mut ui| {
+			ui.text("a");
+			ui.fragment("f").text("c");
+		});
+		hydrate(&MemoryDom, &root, current.children());
+		assert_eq!(root.children(), [first, second]);
+		assert_eq!(outline(&root), "ac");
+	}
+
+	/// Patch `root` and the portals within it from `last` to the result of `render`, returning the new virtual DOM.
+	fn patch_portals_with(
+		root: &MemoryNode,
+		mounts: &mut IdMap<PortalMount<MemoryNode>>,
+		last: &VNodes,
+		render: impl FnOnce(DomBuilder<'_, '_>),
+	) -> VNodes {
+		let current = patch_with(root, last, render);
+		patch_portals(
+			&MemoryDom,
+			mounts,
+			last.children(),
+			current.children(),
+			|_| {},
+		);
+		current
+	}
+
+	#[test]
+	fn portals() {
+		let root = MemoryDom.create_element("div");
+		let target = MemoryDom.create_element("body");
+		let other_target = MemoryDom.create_element("aside");
+		let portal = |ui: &mut DomBuilder<'_, '_>, id: &str, target: &MemoryNode| {
+			ui.portal_in(id, PortalTarget::new(target.clone()), |mut ui| ui.text(id));
+		};
+		let mut mounts = IdMap::default();
+
+		let last = patch_portals_with(&root, &mut mounts, &VNodes::default(), |mut ui| {
+			portal(&mut ui, "a", &target);
+			portal(&mut ui.element("div", "div").children(), "c", &target);
+		});
+		assert_eq!(outline(&root), "<!--portal--><div><!--portal--></div>");
+		assert_eq!(outline(&target), "<div>a</div><div>c</div>");
+		let containers = target.children();
+
+		let last = patch_portals_with(&root, &mut mounts, &last, |mut ui| {
+			portal(&mut ui, "a", &target);
+			portal(&mut ui, "b", &target);
+			portal(&mut ui.element("div", "div").children(), "c", &target);
+		});
+		assert_eq!(outline(&target), "<div>a</div><div>b</div><div>c</div>");
+		assert!(target.children()[0] == containers[0]);
+		assert!(target.children()[2] == containers[1]);
+
+		patch_portals_with(&root, &mut mounts, &last, |mut ui| {
+			portal(&mut ui, "b", &target);
+			portal(&mut ui.element("div", "div").children(), "c", &other_target);
+		});
+		assert_eq!(outline(&target), "<div>b</div>");
+		assert_eq!(outline(&other_target), "<div>c</div>");
+		assert_eq!(containers[0].parent(), None);
+		assert_eq!(mounts.len(), 2);
+	}
+
+	/// Patch like [`patch_with`], also returning the IDs and lifecycles of the notifications in the order they were emitted.
+	fn patch_notified(
+		root: &MemoryNode,
+		last: &VNodes,
+		render: impl FnOnce(DomBuilder<'_, '_>),
+	) -> (VNodes, Vec<(Id, Lifecycle)>) {
+		let current = rebuild(last, render);
+		let notifications = patch(&MemoryDom, root, last.children(), current.children());
+		let lifecycles = notifications
+			.iter()
+			.map(|notification| (notification.id, notification.lifecycle))
+			.collect();
+		(current, lifecycles)
+	}
+
+	#[test]
+	fn notify_id_change_in_place() {
+		// the text keeps the children unkeyed, so the paragraph is patched in place even if its ID changes
+		let render = |id: &'static str| {
+			move |mut ui: DomBuilder<'_, '_>| {
+				ui.text("x");
+				ui.element(id, "p").on_mount(|_| {});
+			}
+		};
+		let (a, b) = (Id::new("a"), Id::new("b"));
+		let root = MemoryDom.create_element("div");
+		let (last, notifications) = patch_notified(&root, &VNodes::default(), render("a"));
+		assert_eq!(notifications, [(a, Lifecycle::Mount)]);
+
+		let (last, notifications) = patch_notified(&root, &last, render("a"));
+		assert_eq!(notifications, [(a, Lifecycle::Update)]);
+
+		let p = root.children()[1].clone();
+		let (_, notifications) = patch_notified(&root, &last, render("b"));
+		assert_eq!(
+			notifications,
+			[(a, Lifecycle::Unmount), (b, Lifecycle::Mount)]
+		);
+		assert!(root.children()[1] == p);
+	}
+
+	#[test]
+	fn notify_keyed_removal_of_descendants() {
+		let render = |ids: &'static [&'static str]| {
+			move |mut ui: DomBuilder<'_, '_>| {
+				for id in ids {
+					ui.element(id, "li")
+						.children()
+						.element("span", "span")
+						.on_unmount(|_| {});
+				}
+			}
+		};
+		let span = |id: &str| Id::new(id).with("span");
+		let root = MemoryDom.create_element("ul");
+		let last = patch_with(&root, &VNodes::default(), render(&["a", "b", "c"]));
+		let removed = root.children()[1].children()[0].clone();
+
+		let current = rebuild(&last, render(&["a", "c"]));
+		let notifications = patch(&MemoryDom, &root, last.children(), current.children());
+		let unmounted: Vec<_> = notifications
+			.iter()
+			.filter(|notification| notification.lifecycle != Lifecycle::Update)
+			.collect();
+		assert_eq!(unmounted.len(), 1);
+		assert_eq!(unmounted[0].id, span("b"));
+		assert_eq!(unmounted[0].lifecycle, Lifecycle::Unmount);
+		assert!(unmounted[0].node == removed);
+	}
+
+	#[test]
+	fn notify_regenerate() {
+		let render = |p: &'static str| {
+			move |mut ui: DomBuilder<'_, '_>| {
+				ui.text("x");
+				ui.element(p, "p").on_mount(|_| {});
+				ui.element("q", "q").on_mount(|_| {});
 			}
-			for node in new {
-				dom.append_child(&node.to_dom()).unwrap();
+		};
+		let (a, b, q) = (Id::new("a"), Id::new("b"), Id::new("q"));
+		let root = MemoryDom.create_element("div");
+		let last = patch_with(&root, &VNodes::default(), render("a"));
+		// removed by other code, so the patch fails once it reaches `q`
+		MemoryDom.remove_child(&root, &root.children()[2]);
+
+		// `a` was unmounted before the patch failed, which is kept, but the mount of `b` is undone
+		// `a` is then unmounted again, as the DOM it is being removed from still had it
+		let (_, notifications) = patch_notified(&root, &last, render("b"));
+		assert_eq!(
+			notifications,
+			[
+				(a, Lifecycle::Unmount),
+				(a, Lifecycle::Unmount),
+				(b, Lifecycle::Mount),
+				(q, Lifecycle::Mount),
+			]
+		);
+		assert_eq!(outline(&root), "x<p></p><q></q>");
+	}
+
+	fn list(ids: &[u32]) -> impl FnOnce(DomBuilder<'_, '_>) + '_ {
+		move |mut ui| {
+			for id in ids {
+				ui.element(id, "li").children().text(id.to_string());
 			}
 		}
 	}
+
+	fn texts(root: &MemoryNode) -> Vec<String> {
+		root
+			.children()
+			.iter()
+			.map(|child| child.children()[0].text().unwrap())
+			.collect()
+	}
+
+	/// Find the node among `before` that rendered `id`.
+	fn handle(before: &[MemoryNode], id: u32) -> &MemoryNode {
+		before
+			.iter()
+			.find(|node| node.children()[0].text().unwrap() == id.to_string())
+			.unwrap()
+	}
+
+	#[test]
+	fn keyed_insert_at_head() {
+		let root = MemoryDom.create_element("ul");
+		let ids: Vec<u32> = (1..=100).collect();
+		let last = patch_with(&root, &VNodes::default(), list(&ids));
+		let before = root.children();
+
+		let mut inserted = vec![0];
+		inserted.extend(&ids);
+		patch_with(&root, &last, list(&inserted));
+		let after = root.children();
+		assert_eq!(
+			texts(&root),
+			inserted.iter().map(u32::to_string).collect::<Vec<_>>()
+		);
+		assert_eq!(after[1..], before[..]);
+	}
+
+	#[test]
+	fn keyed_reorder() {
+		let root = MemoryDom.create_element("ul");
+		let last = patch_with(&root, &VNodes::default(), list(&[1, 2, 3, 4, 5]));
+		let before = root.children();
+
+		let order = [5, 3, 1, 4, 2];
+		patch_with(&root, &last, list(&order));
+		assert_eq!(texts(&root), ["5", "3", "1", "4", "2"]);
+		for (node, id) in root.children().iter().zip(order) {
+			assert!(node == handle(&before, id));
+		}
+	}
+
+	#[test]
+	fn keyed_remove() {
+		let root = MemoryDom.create_element("ul");
+		let last = patch_with(&root, &VNodes::default(), list(&[1, 2, 3, 4]));
+		let before = root.children();
+
+		patch_with(&root, &last, list(&[1, 3]));
+		assert_eq!(texts(&root), ["1", "3"]);
+		assert_eq!(root.children(), [before[0].clone(), before[2].clone()]);
+		assert_eq!(before[1].parent(), None);
+		assert_eq!(before[3].parent(), None);
+	}
+
+	#[test]
+	fn keyed_tag_change() {
+		let root = MemoryDom.create_element("ul");
+		let last = patch_with(&root, &VNodes::default(), list(&[1, 2]));
+		let before = root.children();
+
+		patch_with(&root, &last, |mut ui| {
+			ui.element(1, "li").children().text("1");
+			ui.element(2, "p").children().text("2");
+		});
+		assert_eq!(outline(&root), "<li>1</li><p>2</p>");
+		assert!(root.children()[0] == before[0]);
+		assert!(root.children()[1] != before[1]);
+	}
+
+	#[test]
+	fn duplicate_ids_fall_back_to_unkeyed() {
+		let root = MemoryDom.create_element("ul");
+		let last = patch_with(&root, &VNodes::default(), list(&[1, 2]));
+		let before = root.children();
+
+		// The IDs are no longer unique, so the elements are matched up by position instead.
+		patch_with(&root, &last, list(&[2, 2, 1]));
+		assert_eq!(texts(&root), ["2", "2", "1"]);
+		assert_eq!(root.children()[..2], before[..]);
+	}
 }